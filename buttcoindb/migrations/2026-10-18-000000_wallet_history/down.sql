@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE balance_progress;
+DROP INDEX wallet_waddress;
+ALTER TABLE wallet DROP COLUMN received;
+ALTER TABLE wallet DROP COLUMN sent;
+ALTER TABLE wallet DROP COLUMN first_seen;
+ALTER TABLE wallet DROP COLUMN last_seen;
+ALTER TABLE wallet DROP COLUMN tx_count;
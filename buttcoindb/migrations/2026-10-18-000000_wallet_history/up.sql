@@ -0,0 +1,14 @@
+-- Received, sent and first/last seen heights of every address, kept up to date by `buttcoin balances`
+ALTER TABLE wallet ADD COLUMN received BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE wallet ADD COLUMN sent BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE wallet ADD COLUMN first_seen BIGINT;
+ALTER TABLE wallet ADD COLUMN last_seen BIGINT;
+ALTER TABLE wallet ADD COLUMN tx_count BIGINT NOT NULL DEFAULT 0;
+
+CREATE UNIQUE INDEX wallet_waddress ON wallet (waddress);
+
+-- Last height whose segment is included in the wallet table
+CREATE TABLE balance_progress (
+  id INTEGER NOT NULL PRIMARY KEY,
+  height BIGINT NOT NULL
+);
@@ -1,10 +1,10 @@
 pub mod models;
 pub mod schema;
 
-use diesel;
 use diesel::prelude::*;
 use diesel::result::Error;
 use models::*;
+use schema::balance_progress;
 use schema::transactions::dsl::*;
 use schema::txouts;
 use schema::txouts::dsl::*;
@@ -15,6 +15,13 @@ use std::env;
 pub struct Database {
     conn: SqliteConnection,
 }
+
+impl Default for Database {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Database {
     pub fn new() -> Self {
         let conn = SqliteConnection::establish(env::var("DATABASE_URL").unwrap().as_str())
@@ -22,6 +29,12 @@ impl Database {
         Database { conn }
     }
 
+    pub fn connect(url: &str) -> Result<Self, String> {
+        SqliteConnection::establish(url)
+            .map(|conn| Database { conn })
+            .map_err(|e| format!("Error connecting to {}: {}", url, e))
+    }
+
     pub fn get_transaction(&self, transid: &String) -> Option<Transactions> {
         let result = transactions
             .filter(txid.eq(transid))
@@ -39,7 +52,7 @@ impl Database {
 
     pub fn get_txout_by_txn(&self, transid: &String, txout_vout: i32) -> Option<Txouts> {
         let tx = self
-            .get_transaction(&transid)
+            .get_transaction(transid)
             .expect("Expected transaction to exist");
 
         let result = txouts
@@ -95,7 +108,7 @@ impl Database {
             .execute(&self.conn)
             .expect("Expected wallet insert");
 
-        self.get_wallet(&waladdr)
+        self.get_wallet(waladdr)
             .expect("Expected wallet to exist not")
     }
 
@@ -106,6 +119,80 @@ impl Database {
             .expect("Expected update");
     }
 
+    /// Last height included in the wallet history, None before the first run
+    pub fn get_balance_height(&self) -> Option<i64> {
+        let result = balance_progress::table
+            .find(0)
+            .select(balance_progress::height)
+            .get_result::<i64>(&self.conn);
+
+        match result {
+            Ok(height) => Some(height),
+            Err(err) => match err {
+                Error::NotFound => None,
+                _ => panic!("Unknown error: {}", err),
+            },
+        }
+    }
+
+    /// Add the deltas of a range of heights ending at height to the wallets, in one transaction
+    /// with the progress so that a range is never counted twice
+    pub fn apply_wallet_deltas(&self, deltas: &[WalletDelta], height: i64) {
+        self.conn
+            .transaction::<_, Error, _>(|| {
+                for delta in deltas.iter() {
+                    let wal = match self.get_wallet(delta.waddress) {
+                        Some(wal) => wal,
+                        None => self.insert_wallet(delta.waddress),
+                    };
+                    let first = wal.first_seen.map_or(delta.first_seen, |first| {
+                        first.min(delta.first_seen)
+                    });
+                    let last = wal
+                        .last_seen
+                        .map_or(delta.last_seen, |last| last.max(delta.last_seen));
+                    diesel::update(&wal)
+                        .set((
+                            wallet::balance.eq(wal.balance + delta.received - delta.sent),
+                            wallet::received.eq(wal.received + delta.received),
+                            wallet::sent.eq(wal.sent + delta.sent),
+                            wallet::first_seen.eq(first),
+                            wallet::last_seen.eq(last),
+                            wallet::tx_count.eq(wal.tx_count + delta.tx_count),
+                        ))
+                        .execute(&self.conn)?;
+                }
+                diesel::replace_into(balance_progress::table)
+                    .values((
+                        balance_progress::id.eq(0),
+                        balance_progress::height.eq(height),
+                    ))
+                    .execute(&self.conn)?;
+                Ok(())
+            })
+            .expect("Expected wallet update");
+    }
+
+    /// Clear the wallet history and the progress so that it is computed from genesis again
+    pub fn reset_wallet_history(&self) {
+        self.conn
+            .transaction::<_, Error, _>(|| {
+                diesel::update(wallet)
+                    .set((
+                        wallet::balance.eq(0),
+                        wallet::received.eq(0),
+                        wallet::sent.eq(0),
+                        wallet::first_seen.eq(None::<i64>),
+                        wallet::last_seen.eq(None::<i64>),
+                        wallet::tx_count.eq(0),
+                    ))
+                    .execute(&self.conn)?;
+                diesel::delete(balance_progress::table).execute(&self.conn)?;
+                Ok(())
+            })
+            .expect("Expected wallet reset");
+    }
+
     /***
     pub fn insert_vout(
         &self,
@@ -117,7 +204,7 @@ impl Database {
             .get_transaction(transid)
             .expect("Expected transaction to exist");
 
-        let wal: Wallet = match self.get_wallet(&waladdr) {
+        let wal: Wallet = match self.get_wallet(waladdr) {
             Some(wal) => wal,
             None => {
                 // Create and return wallet
@@ -146,8 +233,7 @@ impl Database {
             .values(tx)
             .execute(&self.conn)
             .expect("Expected successful insert");
-        return self
-            .get_transaction(transactiondid)
-            .expect("Expected successful query after insert");
+        self.get_transaction(transactiondid)
+            .expect("Expected successful query after insert")
     }
 }
@@ -45,6 +45,11 @@ pub struct Wallet {
     pub id: i32,
     pub waddress: String,
     pub balance: i64,
+    pub received: i64,
+    pub sent: i64,
+    pub first_seen: Option<i64>,
+    pub last_seen: Option<i64>,
+    pub tx_count: i64,
 }
 
 #[derive(Insertable)]
@@ -53,3 +58,13 @@ pub struct NewWallet<'a> {
     pub waddress: &'a String,
     pub balance: i64,
 }
+
+/// What happened to an address in a range of heights, added to its wallet row
+pub struct WalletDelta<'a> {
+    pub waddress: &'a String,
+    pub received: i64,
+    pub sent: i64,
+    pub first_seen: i64,
+    pub last_seen: i64,
+    pub tx_count: i64,
+}
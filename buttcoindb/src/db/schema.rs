@@ -1,3 +1,10 @@
+table! {
+    balance_progress (id) {
+        id -> Integer,
+        height -> BigInt,
+    }
+}
+
 table! {
     transactions (id) {
         id -> Integer,
@@ -22,12 +29,18 @@ table! {
         id -> Integer,
         waddress -> Text,
         balance -> BigInt,
+        received -> BigInt,
+        sent -> BigInt,
+        first_seen -> Nullable<BigInt>,
+        last_seen -> Nullable<BigInt>,
+        tx_count -> BigInt,
     }
 }
 
 joinable!(txouts -> transactions (transactions_id));
 
 allow_tables_to_appear_in_same_query!(
+    balance_progress,
     transactions,
     txouts,
     wallet,
@@ -0,0 +1,7 @@
+// diesel 1.4 derives expand to impls inside of functions
+#![allow(non_local_definitions)]
+
+#[macro_use]
+extern crate diesel;
+
+pub mod db;
@@ -0,0 +1,94 @@
+use buttcoindb::db::{models::WalletDelta, Database};
+use diesel::connection::SimpleConnection;
+use diesel::prelude::*;
+use std::path::PathBuf;
+
+const MIGRATIONS: &[&str] = &[
+    include_str!("../migrations/2021-07-01-154127_transactions/up.sql"),
+    include_str!("../migrations/2026-10-18-000000_wallet_history/up.sql"),
+];
+
+/// A fresh database with every migration run, the way `diesel migration run` leaves it
+fn database(name: &str) -> (Database, PathBuf) {
+    let path = std::env::temp_dir().join(format!("buttcoindb-{}-{}.db", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let url = path.to_str().unwrap();
+    let conn = SqliteConnection::establish(url).unwrap();
+    for migration in MIGRATIONS {
+        conn.batch_execute(migration).unwrap();
+    }
+    (Database::connect(url).unwrap(), path)
+}
+
+fn delta(waddress: &String, received: i64, sent: i64, seen: (i64, i64)) -> WalletDelta<'_> {
+    WalletDelta {
+        waddress,
+        received,
+        sent,
+        first_seen: seen.0,
+        last_seen: seen.1,
+        tx_count: 1,
+    }
+}
+
+#[test]
+fn deltas_add_up() {
+    let (db, path) = database("deltas");
+    let alice = "alice".to_string();
+    let bob = "bob".to_string();
+    assert_eq!(db.get_balance_height(), None);
+
+    db.apply_wallet_deltas(&[delta(&alice, 5_000, 0, (3, 7))], 9);
+    assert_eq!(db.get_balance_height(), Some(9));
+    let wallet = db.get_wallet(&alice).unwrap();
+    assert_eq!(
+        (wallet.balance, wallet.received, wallet.sent),
+        (5_000, 5_000, 0)
+    );
+    assert_eq!((wallet.first_seen, wallet.last_seen), (Some(3), Some(7)));
+    assert_eq!(wallet.tx_count, 1);
+
+    // The next segment updates the existing row and adds a new one
+    db.apply_wallet_deltas(
+        &[
+            delta(&alice, 1_000, 4_000, (12, 15)),
+            delta(&bob, 3_000, 0, (12, 12)),
+        ],
+        19,
+    );
+    assert_eq!(db.get_balance_height(), Some(19));
+    let wallet = db.get_wallet(&alice).unwrap();
+    assert_eq!(
+        (wallet.balance, wallet.received, wallet.sent),
+        (2_000, 6_000, 4_000)
+    );
+    assert_eq!((wallet.first_seen, wallet.last_seen), (Some(3), Some(15)));
+    assert_eq!(wallet.tx_count, 2);
+    assert_eq!(db.get_wallet(&bob).unwrap().balance, 3_000);
+
+    // An empty segment only moves the height on
+    db.apply_wallet_deltas(&[], 29);
+    assert_eq!(db.get_balance_height(), Some(29));
+    assert_eq!(db.get_wallet(&alice).unwrap().balance, 2_000);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn reset_starts_over() {
+    let (db, path) = database("reset");
+    let alice = "alice".to_string();
+    db.apply_wallet_deltas(&[delta(&alice, 5_000, 1_000, (3, 7))], 9);
+
+    db.reset_wallet_history();
+    assert_eq!(db.get_balance_height(), None);
+    let wallet = db.get_wallet(&alice).unwrap();
+    assert_eq!((wallet.balance, wallet.received, wallet.sent), (0, 0, 0));
+    assert_eq!((wallet.first_seen, wallet.last_seen), (None, None));
+    assert_eq!(wallet.tx_count, 0);
+
+    db.apply_wallet_deltas(&[delta(&alice, 2_000, 0, (20, 21))], 29);
+    let wallet = db.get_wallet(&alice).unwrap();
+    assert_eq!(wallet.balance, 2_000);
+    assert_eq!((wallet.first_seen, wallet.last_seen), (Some(20), Some(21)));
+    std::fs::remove_file(&path).unwrap();
+}
@@ -1,3 +1,3 @@
 #!/bin/bash
 RUSTFLAGS="-C target-cpu=native" time cargo build --release && \
-	target/release/buttcoin $1 $2 $3
+	target/release/buttcoin "$@"
@@ -0,0 +1,91 @@
+use crate::reader::DataDir;
+use crate::Block;
+use hashbrown::HashMap;
+
+/***
+ * What happened to every address over a run of blocks. Outputs credit the wallet they pay to and
+ * inputs debit the wallet of the output they spend, which needs the prevouts filled in by the
+ * resolve pass. Outputs without an address (bare multisig, OP_RETURN, non-standard scripts) aren't
+ * credited to anyone.
+ */
+#[derive(Clone, Default)]
+pub struct AddressStats {
+    pub received: u64,
+    pub sent: u64,
+    // Heights of the first and last block the address shows up in
+    pub first_seen: u64,
+    pub last_seen: u64,
+    // Transactions paying to or spending from the address
+    pub tx_count: u64,
+}
+
+impl AddressStats {
+    pub fn balance(&self) -> i64 {
+        self.received as i64 - self.sent as i64
+    }
+}
+
+#[derive(Default)]
+pub struct History {
+    pub addresses: HashMap<u64, AddressStats>,
+    // Inputs without a prevout, their wallet isn't debited
+    pub unresolved: u64,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add the next block, first and last seen are taken from the order blocks come in
+    pub fn add_block(&mut self, block: &Block) {
+        let height = block.height;
+        let mut touched: Vec<u64> = Vec::new();
+        for tx in block.transactions.iter() {
+            touched.clear();
+            for vout in tx.vouts.iter() {
+                if let Some(wallet) = vout.wallet {
+                    self.seen(wallet, height).received += vout.value;
+                    touched.push(wallet);
+                }
+            }
+            for vin in tx.vins.iter() {
+                match &vin.prevout {
+                    Some(prevout) => {
+                        if let Some(wallet) = prevout.wallet {
+                            self.seen(wallet, height).sent += prevout.value;
+                            touched.push(wallet);
+                        }
+                    }
+                    None => self.unresolved += 1,
+                }
+            }
+            touched.sort_unstable();
+            touched.dedup();
+            for wallet in touched.iter() {
+                self.seen(*wallet, height).tx_count += 1;
+            }
+        }
+    }
+
+    fn seen(&mut self, wallet: u64, height: u64) -> &mut AddressStats {
+        let stats = self
+            .addresses
+            .entry(wallet)
+            .or_insert_with(|| AddressStats {
+                first_seen: height,
+                ..Default::default()
+            });
+        stats.last_seen = height;
+        stats
+    }
+}
+
+/// The history of every address from genesis up to and including a height
+pub fn history_at(data: &DataDir, height: u64) -> Result<History, String> {
+    let mut history = History::new();
+    for block in data.blocks_up_to(height) {
+        history.add_block(&block?);
+    }
+    Ok(history)
+}
@@ -0,0 +1,368 @@
+use crate::network::NETWORKS;
+use bitcoincore_rpc::bitcoin::{
+    consensus::encode, util::uint::Uint256, Block, BlockHash, BlockHeader, Network,
+};
+use hashbrown::HashMap;
+use log::{info, warn};
+use std::{
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+};
+
+// Each record in a blk file is <magic:4><length:4><block:length>
+const RECORD_PREAMBLE_SIZE: u64 = 8;
+const BLOCK_HEADER_SIZE: usize = 80;
+
+#[derive(Clone, Copy)]
+struct BlockLocation {
+    file: u32,
+    // Offset of the serialized block, right after the record preamble
+    offset: u64,
+    length: u32,
+}
+
+// What the index keeps of a block header until the chain is ordered
+#[derive(Clone, Copy)]
+struct IndexedHeader {
+    prev_hash: BlockHash,
+    // Work of this block alone, from its target
+    work: Uint256,
+    location: BlockLocation,
+}
+
+/***
+ * Index over Bitcoin Core's raw blocks/blk*.dat files.
+ *
+ * The files store blocks in the order they were received, not by height, so opening the index
+ * reads every block header once and orders the blocks by walking the prev_blockhash chain from
+ * the genesis block up to the tip with the most cumulative work, as Bitcoin Core does. Stale
+ * forks and orphans whose parent isn't in the files are left out.
+ */
+pub struct BlkIndex {
+    dir: PathBuf,
+    xor_key: Option<[u8; 8]>,
+    chain: Vec<(BlockHash, BlockLocation)>,
+    heights: HashMap<BlockHash, u64>,
+}
+
+impl BlkIndex {
+    pub fn open(dir: &Path, network: Network) -> Result<Self, String> {
+        let xor_key = read_xor_key(dir)?;
+        let files = list_blk_files(dir)?;
+        if files.is_empty() {
+            return Err(format!("No blk*.dat files found in {}", dir.display()));
+        }
+
+        let magic = network.magic().to_le_bytes();
+        let mut headers: HashMap<BlockHash, IndexedHeader> = HashMap::new();
+        for (file_nr, path) in files.iter() {
+            let nr_headers = headers.len();
+            scan_blk_file(path, *file_nr, &magic, xor_key, &mut headers)?;
+            info!(
+                "Indexed {} ({} headers)",
+                path.display(),
+                headers.len() - nr_headers
+            );
+        }
+
+        let chain = order_chain(&headers)?;
+        info!(
+            "Indexed {} blocks in {} files, best chain height {}",
+            headers.len(),
+            files.len(),
+            chain.len() - 1
+        );
+
+        let heights = chain
+            .iter()
+            .enumerate()
+            .map(|(height, (hash, _))| (*hash, height as u64))
+            .collect();
+
+        Ok(BlkIndex {
+            dir: dir.to_path_buf(),
+            xor_key,
+            chain,
+            heights,
+        })
+    }
+
+    /// Height of the best chain tip
+    pub fn get_tip_height(&self) -> u64 {
+        self.chain.len() as u64 - 1
+    }
+
+    pub fn get_block_hash(&self, height: u64) -> Option<BlockHash> {
+        self.chain.get(height as usize).map(|(hash, _)| *hash)
+    }
+
+    pub fn get_height(&self, hash: &BlockHash) -> Option<u64> {
+        self.heights.get(hash).copied()
+    }
+
+    pub fn get_block(&self, height: u64) -> Result<Block, String> {
+        let (hash, location) = self
+            .chain
+            .get(height as usize)
+            .ok_or_else(|| format!("No block at height {}", height))?;
+
+        let path = self.dir.join(blk_file_name(location.file));
+        let mut reader = BlkFileReader::open(&path, self.xor_key)?;
+        reader.seek(location.offset)?;
+        let mut buf = vec![0u8; location.length as usize];
+        reader.read_exact(&mut buf)?;
+
+        let block: Block = encode::deserialize(&buf)
+            .map_err(|e| format!("Failed to decode block {}: {}", hash, e))?;
+        if block.block_hash() != *hash {
+            return Err(format!(
+                "Block at {}:{} hashes to {}, expected {}",
+                path.display(),
+                location.offset,
+                block.block_hash(),
+                hash
+            ));
+        }
+        Ok(block)
+    }
+}
+
+/***
+ * Reads a blk file, undoing the XOR obfuscation that Bitcoin Core applies since v28 when
+ * blocks/xor.dat holds a non-zero key.
+ */
+struct BlkFileReader {
+    inner: BufReader<File>,
+    pos: u64,
+    len: u64,
+    xor_key: Option<[u8; 8]>,
+}
+
+impl BlkFileReader {
+    fn open(path: &Path, xor_key: Option<[u8; 8]>) -> Result<Self, String> {
+        let file =
+            File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let len = file
+            .metadata()
+            .map_err(|e| format!("Failed to stat {}: {}", path.display(), e))?
+            .len();
+        Ok(BlkFileReader {
+            inner: BufReader::new(file),
+            pos: 0,
+            len,
+            xor_key,
+        })
+    }
+
+    fn remaining(&self) -> u64 {
+        self.len.saturating_sub(self.pos)
+    }
+
+    fn seek(&mut self, pos: u64) -> Result<(), String> {
+        self.inner
+            .seek(SeekFrom::Start(pos))
+            .map_err(|e| format!("Failed to seek: {}", e))?;
+        self.pos = pos;
+        Ok(())
+    }
+
+    fn skip(&mut self, nr_bytes: u64) -> Result<(), String> {
+        self.inner
+            .seek_relative(nr_bytes as i64)
+            .map_err(|e| format!("Failed to seek: {}", e))?;
+        self.pos += nr_bytes;
+        Ok(())
+    }
+
+    fn read_exact(&mut self, buf: &mut [u8]) -> Result<(), String> {
+        self.inner
+            .read_exact(buf)
+            .map_err(|e| format!("Failed to read: {}", e))?;
+        if let Some(key) = self.xor_key {
+            for (i, byte) in buf.iter_mut().enumerate() {
+                *byte ^= key[((self.pos + i as u64) % 8) as usize];
+            }
+        }
+        self.pos += buf.len() as u64;
+        Ok(())
+    }
+}
+
+/// Tell the network of the blk files apart by the magic their first record starts with
+pub fn detect_network(dir: &Path) -> Result<Network, String> {
+    let xor_key = read_xor_key(dir)?;
+    let files = list_blk_files(dir)?;
+    let (_, path) = files
+        .first()
+        .ok_or_else(|| format!("No blk*.dat files found in {}", dir.display()))?;
+    let mut magic = [0u8; 4];
+    BlkFileReader::open(path, xor_key)?.read_exact(&mut magic)?;
+    NETWORKS
+        .iter()
+        .copied()
+        .find(|network| network.magic().to_le_bytes() == magic)
+        .ok_or_else(|| {
+            format!(
+                "{} starts with unknown magic {}",
+                path.display(),
+                hex::encode(magic)
+            )
+        })
+}
+
+fn read_xor_key(dir: &Path) -> Result<Option<[u8; 8]>, String> {
+    let path = dir.join("xor.dat");
+    if !path.exists() {
+        return Ok(None);
+    }
+    let mut key = [0u8; 8];
+    File::open(&path)
+        .and_then(|mut file| file.read_exact(&mut key))
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    if key == [0u8; 8] {
+        return Ok(None);
+    }
+    Ok(Some(key))
+}
+
+fn blk_file_name(file_nr: u32) -> String {
+    format!("blk{:05}.dat", file_nr)
+}
+
+fn list_blk_files(dir: &Path) -> Result<Vec<(u32, PathBuf)>, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to list {}: {}", dir.display(), e))?;
+    let mut files: Vec<(u32, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let file_nr = name
+                .strip_prefix("blk")?
+                .strip_suffix(".dat")?
+                .parse::<u32>()
+                .ok()?;
+            Some((file_nr, entry.path()))
+        })
+        .collect();
+    files.sort_by_key(|(file_nr, _)| *file_nr);
+    Ok(files)
+}
+
+fn scan_blk_file(
+    path: &Path,
+    file_nr: u32,
+    magic: &[u8; 4],
+    xor_key: Option<[u8; 8]>,
+    headers: &mut HashMap<BlockHash, IndexedHeader>,
+) -> Result<(), String> {
+    let mut reader = BlkFileReader::open(path, xor_key)?;
+    let mut preamble = [0u8; RECORD_PREAMBLE_SIZE as usize];
+    let mut header = [0u8; BLOCK_HEADER_SIZE];
+
+    while reader.remaining() >= RECORD_PREAMBLE_SIZE {
+        reader.read_exact(&mut preamble)?;
+        // Bitcoin Core preallocates blk files, the unused tail is zeroed
+        if preamble[0..4] == [0u8; 4] {
+            break;
+        }
+        if preamble[0..4] != magic[..] {
+            return Err(format!(
+                "Unexpected magic {} at {}:{}",
+                hex::encode(&preamble[0..4]),
+                path.display(),
+                reader.pos - RECORD_PREAMBLE_SIZE
+            ));
+        }
+
+        let length = u32::from_le_bytes([preamble[4], preamble[5], preamble[6], preamble[7]]);
+        if (length as usize) < BLOCK_HEADER_SIZE || length as u64 > reader.remaining() {
+            // A record cut short by an unclean shutdown, nothing after it is usable
+            warn!(
+                "Truncated block record at {}:{}",
+                path.display(),
+                reader.pos - RECORD_PREAMBLE_SIZE
+            );
+            break;
+        }
+
+        let offset = reader.pos;
+        reader.read_exact(&mut header)?;
+        let block_header: BlockHeader = encode::deserialize(&header).map_err(|e| {
+            format!(
+                "Failed to decode header at {}:{}: {}",
+                path.display(),
+                offset,
+                e
+            )
+        })?;
+        reader.skip(length as u64 - BLOCK_HEADER_SIZE as u64)?;
+
+        headers.insert(
+            block_header.block_hash(),
+            IndexedHeader {
+                prev_hash: block_header.prev_blockhash,
+                work: block_header.work(),
+                location: BlockLocation {
+                    file: file_nr,
+                    offset,
+                    length,
+                },
+            },
+        );
+    }
+
+    Ok(())
+}
+
+/***
+ * Walk the prev_blockhash links from genesis and return the chain ending in the tip with the most
+ * cumulative work, ordered by height. Of tips with equal work the one stored first in the blk
+ * files wins, the block Bitcoin Core would have received first.
+ */
+fn order_chain(
+    headers: &HashMap<BlockHash, IndexedHeader>,
+) -> Result<Vec<(BlockHash, BlockLocation)>, String> {
+    let mut children: HashMap<BlockHash, Vec<BlockHash>> = HashMap::new();
+    let mut genesis: Option<BlockHash> = None;
+    for (hash, header) in headers.iter() {
+        if header.prev_hash == BlockHash::default() {
+            genesis = Some(*hash);
+        } else {
+            children.entry(header.prev_hash).or_default().push(*hash);
+        }
+    }
+    let genesis = genesis.ok_or_else(|| "Genesis block not found in blk files".to_string())?;
+
+    let stored_at = |hash: &BlockHash| {
+        let location = headers[hash].location;
+        (location.file, location.offset)
+    };
+    let mut best = (genesis, headers[&genesis].work);
+    let mut stack = vec![best];
+    while let Some((hash, work)) = stack.pop() {
+        if work > best.1 || (work == best.1 && stored_at(&hash) < stored_at(&best.0)) {
+            best = (hash, work);
+        }
+        if let Some(next) = children.get(&hash) {
+            stack.extend(
+                next.iter()
+                    .map(|child| (*child, work + headers[child].work)),
+            );
+        }
+    }
+
+    let mut chain = Vec::new();
+    let mut hash = best.0;
+    loop {
+        let header = headers[&hash];
+        chain.push((hash, header.location));
+        if header.prev_hash == BlockHash::default() {
+            break;
+        }
+        hash = header.prev_hash;
+    }
+    chain.reverse();
+
+    Ok(chain)
+}
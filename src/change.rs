@@ -0,0 +1,109 @@
+use crate::{ChangeLabel, Heuristic, Transaction, Vout};
+
+/***
+ * Heuristics for the change output of a transaction, the one paying back to the sender. A
+ * heuristic only labels an output when exactly one output fits it. Only outputs with an address
+ * are candidates, and transactions with fewer than two of them, coinbases and transactions with
+ * unresolved inputs get no labels.
+ *
+ * The confidences are rough relative weights, not measured rates, so that consumers can prefer
+ * one heuristic over another or require several to agree.
+ */
+// A payment to an address that was never seen before while the others were seen
+const NEW_ADDRESS_CONFIDENCE: u8 = 60;
+// The only output that isn't a round amount while the payments are
+const ROUND_AMOUNT_CONFIDENCE: u8 = 70;
+// The only output of the script type all inputs spend
+const SCRIPT_TYPE_CONFIDENCE: u8 = 50;
+// The only output smaller than every input, otherwise an input wouldn't have been needed
+const OPTIMAL_CHANGE_CONFIDENCE: u8 = 50;
+
+// Payments are round at 0.001 BTC
+const ROUND_AMOUNT: u64 = 100_000;
+
+/// Label the change of a transaction, is_new tells whether a wallet shows up for the first time
+pub fn label<F: Fn(u64) -> bool>(tx: &Transaction, is_new: F) -> Vec<ChangeLabel> {
+    let prevouts = match tx
+        .vins
+        .iter()
+        .map(|vin| vin.prevout.as_ref())
+        .collect::<Option<Vec<_>>>()
+    {
+        Some(prevouts) if !prevouts.is_empty() => prevouts,
+        _ => return Vec::new(),
+    };
+    let candidates: Vec<(u32, &Vout, u64)> = tx
+        .vouts
+        .iter()
+        .enumerate()
+        .filter_map(|(idx, vout)| vout.wallet.map(|wallet| (idx as u32, vout, wallet)))
+        .collect();
+    if candidates.len() < 2 {
+        return Vec::new();
+    }
+    // The output if exactly one candidate fits
+    let only = |fits: &dyn Fn(&Vout, u64) -> bool| {
+        let mut fitting = candidates
+            .iter()
+            .filter(|(_, vout, wallet)| fits(vout, *wallet));
+        match (fitting.next(), fitting.next()) {
+            (Some((idx, _, _)), None) => Some(*idx),
+            _ => None,
+        }
+    };
+
+    let mut labels = Vec::new();
+    let mut add = |vout_idx: Option<u32>, heuristic: Heuristic, confidence: u8| {
+        if let Some(vout_idx) = vout_idx {
+            labels.push(ChangeLabel {
+                vout_idx,
+                heuristic,
+                confidence,
+            });
+        }
+    };
+
+    add(
+        only(&|_, wallet| is_new(wallet)),
+        Heuristic::NewAddress,
+        NEW_ADDRESS_CONFIDENCE,
+    );
+    add(
+        only(&|vout, _| vout.value % ROUND_AMOUNT != 0),
+        Heuristic::RoundAmount,
+        ROUND_AMOUNT_CONFIDENCE,
+    );
+    let input_type = prevouts[0].script_type;
+    if prevouts
+        .iter()
+        .all(|prevout| prevout.script_type == input_type)
+    {
+        add(
+            only(&|vout, _| vout.script_type == input_type),
+            Heuristic::ScriptType,
+            SCRIPT_TYPE_CONFIDENCE,
+        );
+    }
+    let smallest_input = prevouts.iter().map(|prevout| prevout.value).min();
+    add(
+        only(&|vout, _| smallest_input.is_some_and(|smallest| vout.value < smallest)),
+        Heuristic::OptimalChange,
+        OPTIMAL_CHANGE_CONFIDENCE,
+    );
+    labels
+}
+
+/// The output the labels agree on with at least min_confidence, None when they point at more
+/// than one output
+pub fn likely_change(labels: &[ChangeLabel], min_confidence: u8) -> Option<u32> {
+    let mut confident = labels
+        .iter()
+        .filter(|label| label.confidence >= min_confidence)
+        .map(|label| label.vout_idx);
+    let first = confident.next()?;
+    if confident.all(|vout_idx| vout_idx == first) {
+        Some(first)
+    } else {
+        None
+    }
+}
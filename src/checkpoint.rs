@@ -0,0 +1,177 @@
+use crate::layout::Layout;
+use crate::segment;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    ops::Range,
+    path::{Path, PathBuf},
+};
+
+const CHECKPOINT_FILE: &str = "checkpoint.json";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CheckpointSegment {
+    pub id: usize,
+    // Inclusive height range of the blocks in the segment
+    pub first_height: u64,
+    pub last_height: u64,
+}
+
+/***
+ * Manifest of the segments that have been durably written to the data directory. A segment is only
+ * recorded once both its blocks and wallets files are synced to disk, so on restart everything in
+ * here can be skipped and anything else has to be processed again.
+ */
+#[derive(Serialize, Deserialize, Default)]
+pub struct Checkpoint {
+    // Missing in checkpoints written before sharding existed, those are flat
+    #[serde(default)]
+    layout: Layout,
+    segments: Vec<CheckpointSegment>,
+}
+
+impl Checkpoint {
+    pub fn load(dir: &Path) -> Result<Self, String> {
+        let path = dir.join(CHECKPOINT_FILE);
+        if !path.exists() {
+            return Ok(Checkpoint::default());
+        }
+        let file =
+            File::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        serde_json::from_reader(file)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    /// Replace the old checkpoint atomically so it is never half written
+    pub fn save(&self, dir: &Path) -> Result<(), String> {
+        segment::write_json(&dir.join(CHECKPOINT_FILE), self)
+    }
+
+    pub fn add_segment(&mut self, id: usize, first_height: u64, last_height: u64) {
+        self.segments.push(CheckpointSegment {
+            id,
+            first_height,
+            last_height,
+        });
+    }
+
+    pub fn get_layout(&self) -> Layout {
+        self.layout
+    }
+
+    /// The layout can only be chosen while the directory holds no segments yet
+    pub fn set_layout(&mut self, layout: Layout) -> Result<(), String> {
+        if layout != self.layout && !self.segments.is_empty() {
+            return Err(format!(
+                "the data directory is {}, not {}",
+                self.layout, layout
+            ));
+        }
+        self.layout = layout;
+        Ok(())
+    }
+
+    pub fn get_segments(&self) -> &[CheckpointSegment] {
+        &self.segments
+    }
+
+    /// Segment numbering continues after the highest recorded segment
+    pub fn next_segment_id(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| segment.id + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// All flushed heights, merged into sorted inclusive ranges
+    pub fn flushed_ranges(&self) -> Vec<(u64, u64)> {
+        merge_ranges(
+            self.segments
+                .iter()
+                // Segments that only carry wallets have an empty range
+                .filter(|segment| segment.first_height <= segment.last_height)
+                .map(|segment| (segment.first_height, segment.last_height))
+                .collect(),
+        )
+    }
+
+    /// Heights in the range that are not part of any recorded segment
+    pub fn pending_heights(&self, heights: Range<u64>) -> Vec<u64> {
+        let flushed = self.flushed_ranges();
+        let mut flushed = flushed.iter().peekable();
+        heights
+            .filter(|height| {
+                while let Some((_, end)) = flushed.peek() {
+                    if *end < *height {
+                        flushed.next();
+                    } else {
+                        break;
+                    }
+                }
+                !matches!(flushed.peek(), Some((start, _)) if start <= height)
+            })
+            .collect()
+    }
+
+    /// Remove segment and temporary files left behind by a run that stopped before they were recorded
+    pub fn remove_unrecorded(&self, dir: &Path) -> Result<(), String> {
+        // Segment files are either in the directory itself or one level down in a shard
+        let mut paths = list_dir(dir)?;
+        let shards: Vec<PathBuf> = paths.iter().filter(|path| path.is_dir()).cloned().collect();
+        for shard in shards {
+            paths.extend(list_dir(&shard)?);
+        }
+        let unrecorded: Vec<PathBuf> = paths
+            .into_iter()
+            .filter(|path| {
+                if path.extension().is_some_and(|ext| ext == "tmp") {
+                    return true;
+                }
+                match segment_id_from_path(path) {
+                    Some(id) => !self.segments.iter().any(|segment| segment.id == id),
+                    None => false,
+                }
+            })
+            .collect();
+
+        for path in unrecorded {
+            warn!("Removing unrecorded segment file {}", path.display());
+            std::fs::remove_file(&path)
+                .map_err(|e| format!("Failed to remove {}: {}", path.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+fn list_dir(dir: &Path) -> Result<Vec<PathBuf>, String> {
+    let entries =
+        std::fs::read_dir(dir).map_err(|e| format!("Failed to list {}: {}", dir.display(), e))?;
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .collect())
+}
+
+/// Sort inclusive ranges and join the ones that overlap or touch
+fn merge_ranges(mut ranges: Vec<(u64, u64)>) -> Vec<(u64, u64)> {
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (start, end) in ranges {
+        match merged.last_mut() {
+            Some(last) if start <= last.1 + 1 => last.1 = last.1.max(end),
+            _ => merged.push((start, end)),
+        }
+    }
+    merged
+}
+
+fn segment_id_from_path(path: &Path) -> Option<usize> {
+    let name = path.file_name()?.to_str()?;
+    let id = name
+        .strip_prefix("blocks-")
+        .or_else(|| name.strip_prefix("wallets-"))?
+        .strip_suffix(".dat")?;
+    id.parse::<usize>().ok()
+}
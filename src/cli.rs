@@ -0,0 +1,234 @@
+use bitcoincore_rpc as bitcoin;
+use buttcoin::ingest::DATA_DIR;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+
+/***
+ * Command line of the buttcoin binary. Every subcommand works on a data directory, only ingest
+ * needs a block source.
+ */
+#[derive(Parser)]
+#[clap(
+    name = "buttcoin",
+    version,
+    about = "Parse the bitcoin blockchain into segment files"
+)]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Parse blocks from a node, blk files or a hex dump into the data directory
+    Ingest(IngestArgs),
+    /// Print a segment, block or transaction from the data directory
+    Inspect {
+        #[clap(flatten)]
+        data: DataArgs,
+        #[clap(subcommand)]
+        command: InspectCommand,
+    },
+    /// Write blocks, wallets or protocol usage of the data directory as JSON, one record per line
+    Export(ExportArgs),
+    /// Fill every input with the wallet and value of the output it spends
+    Resolve {
+        #[clap(flatten)]
+        data: DataArgs,
+    },
+    /// Compute the balance and history of every address into the wallet table of the database
+    Balances(BalancesArgs),
+    /// Group addresses spent together into clusters and write them to clusters.dat
+    Cluster(ClusterArgs),
+    /// Print the richest addresses and the distribution of balances at a height
+    Report(ReportArgs),
+    /// Build the UTXO set at a height from the segments and compare it with the node's
+    Utxo(UtxoArgs),
+    /// Check the coinbases against the subsidy schedule and compare the issued supply with it
+    Audit(AuditArgs),
+    /// Read back every checkpointed segment and check its checksum and height range
+    Verify {
+        #[clap(flatten)]
+        data: DataArgs,
+    },
+}
+
+#[derive(Args)]
+pub struct DataArgs {
+    /// Directory holding the segment files and the checkpoint
+    #[clap(long, env = "BUTTCOIN_DATA_DIR", default_value = DATA_DIR)]
+    pub data_dir: PathBuf,
+}
+
+#[derive(Args)]
+pub struct IngestArgs {
+    #[clap(flatten)]
+    pub data: DataArgs,
+    /// Worker threads, 0 uses one per core
+    #[clap(short, long, default_value_t = 0)]
+    pub threads: usize,
+    /// Transactions per segment before it is flushed, approximately
+    #[clap(long, default_value_t = 500_000)]
+    pub threshold: u64,
+    /// Blocks fetched and processed together by a worker, at least 1
+    #[clap(long, default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+    pub chunk_size: u64,
+    /// Put segments in subdirectories per this many heights, e.g. 100000. A data directory that
+    /// already holds segments keeps its layout and refuses a different one
+    #[clap(long)]
+    pub shard_size: Option<u64>,
+    /// First height to process
+    #[clap(long, default_value_t = 0)]
+    pub start_height: u64,
+    /// Stop before this height, defaults to one above the tip so the tip is ingested
+    #[clap(long)]
+    pub end_height: Option<u64>,
+    /// bitcoin, testnet, signet or regtest. Detected from the node, the blk files or the genesis
+    /// block when not given
+    #[clap(long)]
+    pub network: Option<bitcoin::bitcoin::Network>,
+    #[clap(flatten)]
+    pub source: SourceArgs,
+}
+
+/// The blk files or the hex dump are used when given, otherwise blocks are fetched over RPC
+#[derive(Args)]
+pub struct SourceArgs {
+    /// Bitcoin Core's blocks directory, read directly without a running node
+    #[clap(long, env = "BITCOIN_BLOCKS_DIR")]
+    pub blocks_dir: Option<PathBuf>,
+    /// File with one hex encoded block per line, starting at genesis
+    #[clap(long, env = "BITCOIN_BLOCKS_HEX", conflicts_with = "blocks-dir")]
+    pub blocks_hex: Option<PathBuf>,
+    #[clap(flatten)]
+    pub rpc: RpcArgs,
+}
+
+#[derive(Args)]
+pub struct RpcArgs {
+    #[clap(long, env = "BITCOINRPC_URL", default_value = "http://127.0.0.1:8332")]
+    pub rpc_url: String,
+    #[clap(long, env = "BITCOINRPC_USER")]
+    pub rpc_user: Option<String>,
+    #[clap(long, env = "BITCOINRPC_PASS", hide_env_values = true)]
+    pub rpc_pass: Option<String>,
+}
+
+#[derive(Args)]
+pub struct BalancesArgs {
+    #[clap(flatten)]
+    pub data: DataArgs,
+    /// SQLite database with the buttcoindb migrations applied
+    #[clap(long, env = "DATABASE_URL")]
+    pub database_url: String,
+    /// Only add the segments above the height of the last run instead of starting over
+    #[clap(long)]
+    pub incremental: bool,
+}
+
+#[derive(Args)]
+pub struct ClusterArgs {
+    #[clap(flatten)]
+    pub data: DataArgs,
+    /// Last height to include, defaults to the last height ingested from genesis
+    #[clap(long)]
+    pub height: Option<u64>,
+    /// Merge the inputs of transactions flagged as CoinJoin or PayJoin too
+    #[clap(long)]
+    pub merge_collaborative: bool,
+    /// Don't merge the inputs of transactions with this many outputs of the same value and at
+    /// least as many inputs, 0 merges them
+    #[clap(long, default_value_t = 3)]
+    pub equal_outputs: usize,
+    /// Don't merge the inputs of transactions with more inputs than this
+    #[clap(long)]
+    pub max_inputs: Option<usize>,
+    /// Also merge the change output when its labels agree on it with at least this confidence in
+    /// percent
+    #[clap(long)]
+    pub change_confidence: Option<u8>,
+    /// Number of clusters to print, largest balance first
+    #[clap(long, default_value_t = 20)]
+    pub top: usize,
+}
+
+#[derive(Args)]
+pub struct ReportArgs {
+    #[clap(flatten)]
+    pub data: DataArgs,
+    /// Height to report on, defaults to the last height ingested from genesis
+    #[clap(long)]
+    pub height: Option<u64>,
+    /// Number of addresses in the rich list
+    #[clap(long, default_value_t = 100)]
+    pub top: usize,
+}
+
+#[derive(Args)]
+pub struct AuditArgs {
+    #[clap(flatten)]
+    pub data: DataArgs,
+    /// Height to audit up to, defaults to the last height ingested from genesis
+    #[clap(long)]
+    pub height: Option<u64>,
+    /// Number of blocks listed per kind of finding
+    #[clap(long, default_value_t = 20)]
+    pub list: usize,
+}
+
+#[derive(Args)]
+pub struct UtxoArgs {
+    #[clap(flatten)]
+    pub data: DataArgs,
+    /// Height of the snapshot, defaults to the last height ingested from genesis or the node's
+    /// tip with --check
+    #[clap(long)]
+    pub height: Option<u64>,
+    /// Megabytes of memory for the set, larger sets are built in several passes
+    #[clap(long)]
+    pub memory_cap: Option<u64>,
+    /// Compare the totals with gettxoutsetinfo of the node
+    #[clap(long)]
+    pub check: bool,
+    #[clap(flatten)]
+    pub rpc: RpcArgs,
+}
+
+#[derive(Subcommand)]
+pub enum InspectCommand {
+    /// Header and totals of a segment
+    Segment { id: usize },
+    /// A block and its transactions
+    Block {
+        /// Block hash or height
+        block: String,
+    },
+    /// A transaction with the addresses of its outputs
+    Tx { txid: String },
+}
+
+#[derive(Args)]
+pub struct ExportArgs {
+    #[clap(flatten)]
+    pub data: DataArgs,
+    /// What to export
+    #[clap(arg_enum)]
+    pub what: ExportKind,
+    /// First height of the blocks to export, wallets are always exported in full
+    #[clap(long, default_value_t = 0)]
+    pub start_height: u64,
+    /// Stop before this height
+    #[clap(long)]
+    pub end_height: Option<u64>,
+    /// Write to a file instead of stdout
+    #[clap(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+#[derive(clap::ArgEnum, Clone, Copy)]
+pub enum ExportKind {
+    Blocks,
+    Wallets,
+    /// Embedded data outputs and inputs per protocol and block
+    Protocols,
+}
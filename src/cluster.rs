@@ -0,0 +1,348 @@
+use crate::balance::History;
+use crate::change;
+use crate::reader::DataDir;
+use crate::segment::{self, FileHeader, FileKind};
+use crate::{Block, Transaction};
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/***
+ * Groups addresses into entities with the common-input-ownership heuristic: every address spent
+ * from in one transaction is assumed to belong to the same owner. Transactions where that doesn't
+ * hold, like CoinJoins that combine inputs of many owners, are left out by the exclusions, the
+ * addresses in them still get a cluster of their own.
+ *
+ * The change output of a transaction can be merged with its inputs too, when the change labels
+ * agree on it with enough confidence.
+ *
+ * The result is kept in clusters.dat in the data directory, a map of every wallet hash to its
+ * cluster and the totals of every cluster. Clusters are numbered in the order their first address
+ * showed up.
+ */
+const CLUSTERS_FILE: &str = "clusters.dat";
+
+/// A kind of transaction whose inputs aren't merged
+pub trait Exclusion: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn excludes(&self, tx: &Transaction) -> bool;
+}
+
+pub struct Exclusions {
+    exclusions: Vec<Box<dyn Exclusion>>,
+}
+
+impl Default for Exclusions {
+    /// Leave out flagged CoinJoins and PayJoins, and anything else that looks like an equal output
+    /// CoinJoin
+    fn default() -> Self {
+        Exclusions {
+            exclusions: vec![
+                Box::new(CollaborativeTransactions),
+                Box::new(EqualOutputs { min_outputs: 3 }),
+            ],
+        }
+    }
+}
+
+impl Exclusions {
+    pub fn empty() -> Self {
+        Exclusions {
+            exclusions: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, exclusion: Box<dyn Exclusion>) {
+        self.exclusions.push(exclusion);
+    }
+
+    /// Name of the first exclusion that applies to the transaction
+    pub fn excludes(&self, tx: &Transaction) -> Option<&'static str> {
+        self.exclusions
+            .iter()
+            .find(|exclusion| exclusion.excludes(tx))
+            .map(|exclusion| exclusion.name())
+    }
+}
+
+/// At least min_outputs outputs of the same value and as many inputs, the shape of a CoinJoin
+pub struct EqualOutputs {
+    pub min_outputs: usize,
+}
+
+impl Exclusion for EqualOutputs {
+    fn name(&self) -> &'static str {
+        "equal-outputs"
+    }
+
+    fn excludes(&self, tx: &Transaction) -> bool {
+        if tx.vins.len() < self.min_outputs {
+            return false;
+        }
+        let mut counts: HashMap<u64, usize> = HashMap::new();
+        tx.vouts.iter().any(|vout| {
+            let count = counts.entry(vout.value).or_insert(0);
+            *count += 1;
+            *count >= self.min_outputs
+        })
+    }
+}
+
+/// Transactions flagged as CoinJoin or PayJoin when they were parsed and resolved
+pub struct CollaborativeTransactions;
+
+impl Exclusion for CollaborativeTransactions {
+    fn name(&self) -> &'static str {
+        "collaborative"
+    }
+
+    fn excludes(&self, tx: &Transaction) -> bool {
+        tx.collaborative.is_some()
+    }
+}
+
+/// More inputs than a single wallet usually spends at once, e.g. exchange consolidations
+pub struct MaxInputs {
+    pub max_inputs: usize,
+}
+
+impl Exclusion for MaxInputs {
+    fn name(&self) -> &'static str {
+        "max-inputs"
+    }
+
+    fn excludes(&self, tx: &Transaction) -> bool {
+        tx.vins.len() > self.max_inputs
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct ClusterStats {
+    pub id: u32,
+    pub addresses: u64,
+    pub balance: i64,
+    pub received: u64,
+    pub sent: u64,
+    pub first_seen: u64,
+    pub last_seen: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Clustering {
+    // Last height included
+    pub height: u64,
+    // Indexed by cluster id
+    pub clusters: Vec<ClusterStats>,
+    // Wallet hash and cluster id, sorted by wallet hash
+    pub addresses: Vec<(u64, u32)>,
+    // Transactions whose inputs weren't merged, per exclusion
+    pub excluded: BTreeMap<String, u64>,
+    // Inputs without a prevout, their address couldn't be merged
+    pub unresolved: u64,
+}
+
+impl Clustering {
+    pub fn path(data_dir: &Path) -> PathBuf {
+        data_dir.join(CLUSTERS_FILE)
+    }
+
+    pub fn load(data_dir: &Path) -> Result<Self, String> {
+        segment::read_file(&Self::path(data_dir), FileKind::Clusters)
+            .map(|(_, clustering)| clustering)
+    }
+
+    pub fn save(&self, data_dir: &Path, network: String) -> Result<(), String> {
+        let header = FileHeader {
+            kind: FileKind::Clusters,
+            network,
+            segment_id: 0,
+            first_height: 0,
+            last_height: self.height,
+            nr_records: self.addresses.len() as u64,
+            nr_transactions: 0,
+            body_length: 0,
+        };
+        segment::write_file(&Self::path(data_dir), header, self)
+    }
+
+    pub fn cluster_of(&self, wallet: u64) -> Option<u32> {
+        self.addresses
+            .binary_search_by_key(&wallet, |(hash, _)| *hash)
+            .ok()
+            .map(|idx| self.addresses[idx].1)
+    }
+}
+
+/// Disjoint sets of wallet indexes, with union by rank and path halving
+#[derive(Default)]
+pub struct UnionFind {
+    parent: Vec<u32>,
+    rank: Vec<u8>,
+}
+
+impl UnionFind {
+    /// Add a set of its own, returns its index
+    pub fn add(&mut self) -> u32 {
+        let idx = self.parent.len() as u32;
+        self.parent.push(idx);
+        self.rank.push(0);
+        idx
+    }
+
+    pub fn find(&mut self, mut idx: u32) -> u32 {
+        while self.parent[idx as usize] != idx {
+            let grandparent = self.parent[self.parent[idx as usize] as usize];
+            self.parent[idx as usize] = grandparent;
+            idx = grandparent;
+        }
+        idx
+    }
+
+    pub fn union(&mut self, a: u32, b: u32) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return;
+        }
+        let (rank_a, rank_b) = (self.rank[a as usize], self.rank[b as usize]);
+        if rank_a < rank_b {
+            self.parent[a as usize] = b;
+        } else {
+            self.parent[b as usize] = a;
+            if rank_a == rank_b {
+                self.rank[a as usize] += 1;
+            }
+        }
+    }
+}
+
+pub struct Clusterer {
+    exclusions: Exclusions,
+    // Wallet hash to its set, in the order wallets showed up
+    wallets: HashMap<u64, u32>,
+    order: Vec<u64>,
+    sets: UnionFind,
+    history: History,
+    excluded: BTreeMap<String, u64>,
+    // Merge change outputs labelled with at least this confidence
+    change_confidence: Option<u8>,
+}
+
+impl Clusterer {
+    pub fn new(exclusions: Exclusions) -> Self {
+        Clusterer {
+            exclusions,
+            wallets: HashMap::new(),
+            order: Vec::new(),
+            sets: UnionFind::default(),
+            history: History::new(),
+            excluded: BTreeMap::new(),
+            change_confidence: None,
+        }
+    }
+
+    pub fn with_change(mut self, min_confidence: u8) -> Self {
+        self.change_confidence = Some(min_confidence);
+        self
+    }
+
+    /// Add the next block, an input can only be merged once the block paying it was added
+    pub fn add_block(&mut self, block: &Block) {
+        self.history.add_block(block);
+        for tx in block.transactions.iter() {
+            for vout in tx.vouts.iter() {
+                if let Some(wallet) = vout.wallet {
+                    self.set_of(wallet);
+                }
+            }
+            // A coinbase has no inputs to merge
+            if tx.vins.is_empty() {
+                continue;
+            }
+            if let Some(name) = self.exclusions.excludes(tx) {
+                *self.excluded.entry(name.to_string()).or_insert(0) += 1;
+                continue;
+            }
+            let mut inputs = tx
+                .vins
+                .iter()
+                .filter_map(|vin| vin.prevout.as_ref().and_then(|prevout| prevout.wallet));
+            let first = match inputs.next() {
+                Some(first) => self.set_of(first),
+                None => continue,
+            };
+            for wallet in inputs {
+                let set = self.set_of(wallet);
+                self.sets.union(first, set);
+            }
+            let change = self
+                .change_confidence
+                .and_then(|min_confidence| change::likely_change(&tx.change, min_confidence))
+                .and_then(|vout_idx| tx.vouts[vout_idx as usize].wallet);
+            if let Some(wallet) = change {
+                let set = self.set_of(wallet);
+                self.sets.union(first, set);
+            }
+        }
+    }
+
+    fn set_of(&mut self, wallet: u64) -> u32 {
+        match self.wallets.get(&wallet) {
+            Some(set) => *set,
+            None => {
+                let set = self.sets.add();
+                self.wallets.insert(wallet, set);
+                self.order.push(wallet);
+                set
+            }
+        }
+    }
+
+    pub fn finish(mut self, height: u64) -> Clustering {
+        let mut ids: HashMap<u32, u32> = HashMap::new();
+        let mut clusters: Vec<ClusterStats> = Vec::new();
+        let mut addresses: Vec<(u64, u32)> = Vec::with_capacity(self.order.len());
+        for wallet in self.order.iter() {
+            let root = self.sets.find(self.wallets[wallet]);
+            let id = *ids.entry(root).or_insert_with(|| {
+                clusters.push(ClusterStats {
+                    id: clusters.len() as u32,
+                    first_seen: u64::MAX,
+                    ..Default::default()
+                });
+                clusters.len() as u32 - 1
+            });
+            let cluster = &mut clusters[id as usize];
+            cluster.addresses += 1;
+            if let Some(stats) = self.history.addresses.get(wallet) {
+                cluster.balance += stats.balance();
+                cluster.received += stats.received;
+                cluster.sent += stats.sent;
+                cluster.first_seen = cluster.first_seen.min(stats.first_seen);
+                cluster.last_seen = cluster.last_seen.max(stats.last_seen);
+            }
+            addresses.push((*wallet, id));
+        }
+        addresses.sort_unstable_by_key(|(wallet, _)| *wallet);
+        Clustering {
+            height,
+            clusters,
+            addresses,
+            excluded: self.excluded,
+            unresolved: self.history.unresolved,
+        }
+    }
+}
+
+/// Cluster the addresses of every block up to and including a height
+pub fn cluster(
+    data: &DataDir,
+    height: u64,
+    mut clusterer: Clusterer,
+) -> Result<Clustering, String> {
+    for block in data.blocks_up_to(height) {
+        clusterer.add_block(&block?);
+    }
+    Ok(clusterer.finish(height))
+}
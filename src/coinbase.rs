@@ -0,0 +1,88 @@
+use crate::network;
+use crate::script::small_int;
+use crate::{BlockFees, Coinbase};
+use bitcoincore_rpc as bitcoin;
+use bitcoincore_rpc::bitcoin::blockdata::script::Instruction;
+
+/***
+ * The coinbase script of a block is free for the miner to fill. Since BIP34 it starts with the
+ * height, before that Bitcoin Core's miner started it with the difficulty bits. Miners put the
+ * extranonce they roll while hashing after that, and pools tag their blocks with a readable name.
+ *
+ * The claimed amount is what the coinbase outputs pay. It may be less than the subsidy plus the
+ * fees, what's left is never issued.
+ */
+// Shorter runs of printable bytes are mostly chance in binary data
+const MIN_TAG_LEN: usize = 4;
+
+/// Parse the coinbase of a block at a height
+pub fn parse(
+    tx: &bitcoin::bitcoin::Transaction,
+    network: bitcoin::bitcoin::Network,
+    height: u64,
+) -> Coinbase {
+    let script = match tx.input.first() {
+        Some(input) => &input.script_sig,
+        None => return Coinbase::default(),
+    };
+    let mut pushes = script.instructions();
+    let height_push = match pushes.next() {
+        Some(Ok(instruction)) => number(&instruction),
+        _ => None,
+    };
+    let extranonce = match pushes.next() {
+        Some(Ok(Instruction::PushBytes(bytes))) => bytes.to_vec(),
+        _ => Vec::new(),
+    };
+
+    Coinbase {
+        height: height_push,
+        extranonce,
+        tag: tag(script),
+        subsidy: network::subsidy(network, height),
+        claimed: tx.output.iter().map(|output| output.value).sum(),
+        under_claimed: None,
+    }
+}
+
+/// The subsidy plus fees the coinbase didn't claim, None when it claimed more
+pub fn under_claimed(coinbase: &Coinbase, fees: &BlockFees) -> Option<u64> {
+    (coinbase.subsidy + fees.total).checked_sub(coinbase.claimed)
+}
+
+/// A non-negative script number, minimally encoded or not, or OP_1 to OP_16
+fn number(instruction: &Instruction) -> Option<u64> {
+    match instruction {
+        Instruction::PushBytes(bytes) if !bytes.is_empty() && bytes.len() <= 8 => {
+            // The top bit of the last byte is the sign
+            if bytes[bytes.len() - 1] & 0x80 != 0 {
+                return None;
+            }
+            Some(
+                bytes
+                    .iter()
+                    .rev()
+                    .fold(0, |number, byte| number << 8 | *byte as u64),
+            )
+        }
+        instruction => small_int(instruction).map(u64::from),
+    }
+}
+
+/// Runs of printable ASCII in the pushes of the script, separated by a space. A push that runs
+/// past the end of the script ends it
+fn tag(script: &bitcoin::bitcoin::Script) -> String {
+    script
+        .instructions()
+        .map_while(Result::ok)
+        .filter_map(|instruction| match instruction {
+            Instruction::PushBytes(bytes) => Some(bytes),
+            Instruction::Op(_) => None,
+        })
+        .flat_map(|bytes| bytes.split(|byte| !(0x20..0x7f).contains(byte)))
+        .filter(|run| run.len() >= MIN_TAG_LEN)
+        .map(|run| String::from_utf8_lossy(run).trim().to_string())
+        .filter(|run| !run.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
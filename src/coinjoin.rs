@@ -0,0 +1,131 @@
+use crate::{Collaborative, ScriptType, Transaction};
+use hashbrown::HashMap;
+use std::ops::RangeInclusive;
+
+/***
+ * Recognises transactions combining the inputs of several owners from their shape: the number of
+ * inputs and outputs and the values of the outputs. The checks run from the most to the least
+ * specific and the first match is the guess. Coinbases are never collaborative.
+ *
+ * A PayJoin looks like a regular payment on purpose, so it is only flagged as a candidate, and
+ * only once the inputs are resolved as it needs their values.
+ */
+// Whirlpool pools mix 5 inputs into 5 outputs of the pool denomination
+const WHIRLPOOL_DENOMINATIONS: [u64; 4] = [100_000, 1_000_000, 5_000_000, 50_000_000];
+const WHIRLPOOL_PARTICIPANTS: usize = 5;
+
+// Wasabi 1 rounds mix into many outputs of about 0.1 BTC
+const WASABI1_DENOMINATION: RangeInclusive<u64> = 9_000_000..=11_000_000;
+const WASABI1_MIN_EQUAL: usize = 10;
+// Wasabi 2 rounds have hundreds of inputs and outputs, most of them standard denominations
+const WASABI2_MIN_PARTICIPANTS: usize = 50;
+const WASABI2_MIN_DENOMINATION: u64 = 5_000;
+
+// JoinMarket and unknown coordinators, at least this many outputs of the same value
+const MIN_EQUAL_OUTPUTS: usize = 3;
+
+/// Guess whether and how a transaction was built by several parties
+pub fn detect(tx: &Transaction) -> Option<Collaborative> {
+    let nr_inputs = tx.vins.len();
+    let nr_outputs = tx.vouts.len();
+    if nr_inputs == 0 {
+        return None;
+    }
+    let (value, nr_equal) = most_common_value(tx)?;
+
+    if nr_inputs == WHIRLPOOL_PARTICIPANTS
+        && nr_outputs == WHIRLPOOL_PARTICIPANTS
+        && nr_equal == WHIRLPOOL_PARTICIPANTS
+        && WHIRLPOOL_DENOMINATIONS.contains(&value)
+    {
+        return Some(Collaborative::Whirlpool);
+    }
+    if nr_equal >= WASABI1_MIN_EQUAL
+        && nr_inputs >= nr_equal
+        && WASABI1_DENOMINATION.contains(&value)
+    {
+        return Some(Collaborative::Wasabi);
+    }
+    if nr_inputs >= WASABI2_MIN_PARTICIPANTS && nr_outputs >= WASABI2_MIN_PARTICIPANTS {
+        let nr_standard = tx
+            .vouts
+            .iter()
+            .filter(|vout| is_standard_denomination(vout.value))
+            .count();
+        if nr_standard * 2 >= nr_outputs {
+            return Some(Collaborative::Wasabi);
+        }
+    }
+    if nr_equal >= MIN_EQUAL_OUTPUTS && nr_inputs >= nr_equal {
+        // Every maker gets one equal output and one change output, the taker may not have change
+        if nr_outputs == 2 * nr_equal || nr_outputs == 2 * nr_equal - 1 {
+            return Some(Collaborative::JoinMarket);
+        }
+        return Some(Collaborative::CoinJoin);
+    }
+    if is_payjoin_candidate(tx) {
+        return Some(Collaborative::PayJoin);
+    }
+    None
+}
+
+/// The output value paid most often and how often, the larger value on a tie. OP_RETURN outputs
+/// don't pay anyone
+fn most_common_value(tx: &Transaction) -> Option<(u64, usize)> {
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for vout in tx
+        .vouts
+        .iter()
+        .filter(|vout| vout.script_type != ScriptType::OpReturn)
+    {
+        *counts.entry(vout.value).or_insert(0) += 1;
+    }
+    counts
+        .into_iter()
+        .max_by_key(|(value, count)| (*count, *value))
+}
+
+/***
+ * Two outputs, inputs and outputs all of one script type, and an input larger than either output.
+ * A single wallet paying one output would not have needed the other inputs, in a PayJoin they are
+ * the receiver's.
+ */
+fn is_payjoin_candidate(tx: &Transaction) -> bool {
+    if tx.vins.len() < 2 || tx.vouts.len() != 2 {
+        return false;
+    }
+    let prevouts = match tx
+        .vins
+        .iter()
+        .map(|vin| vin.prevout.as_ref())
+        .collect::<Option<Vec<_>>>()
+    {
+        Some(prevouts) => prevouts,
+        None => return false,
+    };
+    let script_type = prevouts[0].script_type;
+    let largest_input = prevouts.iter().map(|prevout| prevout.value).max();
+    let largest_output = tx.vouts.iter().map(|vout| vout.value).max();
+    prevouts
+        .iter()
+        .all(|prevout| prevout.script_type == script_type)
+        && tx.vouts.iter().all(|vout| vout.script_type == script_type)
+        && largest_input > largest_output
+}
+
+/// Wasabi 2 denominations: powers of 2 and 3, twice powers of 3, and 1, 2 and 5 times powers of 10
+fn is_standard_denomination(value: u64) -> bool {
+    if value < WASABI2_MIN_DENOMINATION {
+        return false;
+    }
+    let strip = |mut value: u64, base: u64| {
+        while value.is_multiple_of(base) {
+            value /= base;
+        }
+        value
+    };
+    value.is_power_of_two()
+        || strip(value, 3) == 1
+        || (value.is_multiple_of(2) && strip(value / 2, 3) == 1)
+        || matches!(strip(value, 10), 1 | 2 | 5)
+}
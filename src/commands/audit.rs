@@ -0,0 +1,63 @@
+use crate::cli::AuditArgs;
+use buttcoin::reader::DataDir;
+use buttcoin::supply;
+use log::warn;
+
+/// Audit the coinbases up to a height and print the issued supply against the schedule
+pub fn audit(data: &DataDir, args: &AuditArgs) -> Result<(), String> {
+    let height = match args.height.or_else(|| data.contiguous_height()) {
+        Some(height) => height,
+        None => return Err("No heights are ingested from genesis".to_string()),
+    };
+    let audit = supply::audit(data, height)?;
+    if audit.blocks != height + 1 {
+        warn!(
+            "Only {} of the {} heights up to {} are ingested",
+            audit.blocks,
+            height + 1,
+            height
+        );
+    }
+    if audit.unresolved > 0 {
+        warn!(
+            "The fees of {} blocks are unknown and their issuance estimated, run resolve first",
+            audit.unresolved
+        );
+    }
+
+    let theoretical = audit.theoretical();
+    println!("Supply at height {}", height);
+    println!("  Blocks:        {}", audit.blocks);
+    println!("  Theoretical:   {}", theoretical);
+    println!("  Issued:        {}", audit.issued);
+    println!(
+        "  Shortfall:     {}",
+        theoretical as i64 - audit.issued as i64
+    );
+    println!("  Unclaimed:     {}", audit.under_claimed);
+    println!("  Eras:");
+    for era in audit.eras.iter() {
+        println!(
+            "    {:>8} {:>12} {:>8} {:>20} {:>20}",
+            era.first_height, era.subsidy, era.blocks, era.expected, era.issued
+        );
+    }
+    print_heights("No BIP34 height", &audit.bip34_mismatches, args.list);
+    print_amounts("Over claimed", &audit.over_claimed_blocks, args.list);
+    print_amounts("Under claimed", &audit.under_claimed_blocks, args.list);
+    Ok(())
+}
+
+fn print_heights(title: &str, heights: &[u64], list: usize) {
+    println!("  {}: {}", title, heights.len());
+    for height in heights.iter().take(list) {
+        println!("    {:>8}", height);
+    }
+}
+
+fn print_amounts(title: &str, blocks: &[(u64, u64)], list: usize) {
+    println!("  {}: {}", title, blocks.len());
+    for (height, amount) in blocks.iter().take(list) {
+        println!("    {:>8} {:>20}", height, amount);
+    }
+}
@@ -0,0 +1,113 @@
+use crate::cli::BalancesArgs;
+use buttcoin::balance::History;
+use buttcoin::checkpoint::CheckpointSegment;
+use buttcoin::reader::DataDir;
+use buttcoindb::db::{models::WalletDelta, Database};
+use hashbrown::{HashMap, HashSet};
+use log::{info, warn};
+
+/***
+ * Add the history of every address to the wallet table, one segment per database transaction.
+ * The last height written is kept in the database, so an incremental run continues with the
+ * segment after it and an interrupted run never counts a segment twice. Without --incremental the
+ * history is cleared and computed from genesis.
+ *
+ * Segments only know the hashes of addresses while the table is keyed by the address itself. A
+ * first pass over the segments to apply collects the hashes they touch and only those are looked
+ * up in the wallet files, so an incremental run holds the addresses of a few segments, not of the
+ * whole chain.
+ */
+pub fn balances(data: &DataDir, args: &BalancesArgs) -> Result<(), String> {
+    let db = Database::connect(&args.database_url)?;
+    if !args.incremental {
+        db.reset_wallet_history();
+    }
+    let start_height = db
+        .get_balance_height()
+        .map_or(0, |height| height as u64 + 1);
+
+    // The segments after the database's height, up to the first gap in the ingested heights
+    let mut pending: Vec<&CheckpointSegment> = Vec::new();
+    let mut next_height = start_height;
+    for recorded in data.get_segments() {
+        if recorded.first_height > recorded.last_height || recorded.last_height < next_height {
+            continue;
+        }
+        if recorded.first_height != next_height {
+            warn!(
+                "Heights {}..{} are not ingested, stopping before them",
+                next_height, recorded.first_height
+            );
+            break;
+        }
+        pending.push(recorded);
+        next_height = recorded.last_height + 1;
+    }
+
+    let mut touched: HashSet<u64> = HashSet::new();
+    for recorded in pending.iter() {
+        touched.extend(segment_history(data, recorded)?.addresses.keys());
+    }
+    let touched: Vec<u64> = touched.into_iter().collect();
+    let addresses: HashMap<u64, String> = data
+        .find_wallets(&touched)?
+        .into_iter()
+        .map(|wallet| (wallet.hash, wallet.address))
+        .collect();
+    info!(
+        "Found the addresses of {} wallets in {} segments",
+        addresses.len(),
+        pending.len()
+    );
+
+    for recorded in pending.iter() {
+        let history = segment_history(data, recorded)?;
+        if history.unresolved > 0 {
+            warn!(
+                "{} inputs of segment {} are unresolved, run resolve to debit their wallets",
+                history.unresolved, recorded.id
+            );
+        }
+
+        let deltas = history
+            .addresses
+            .iter()
+            .map(|(hash, stats)| {
+                let waddress = addresses
+                    .get(hash)
+                    .ok_or_else(|| format!("Wallet {:016x} is in no wallets file", hash))?;
+                Ok(WalletDelta {
+                    waddress,
+                    received: stats.received as i64,
+                    sent: stats.sent as i64,
+                    first_seen: stats.first_seen as i64,
+                    last_seen: stats.last_seen as i64,
+                    tx_count: stats.tx_count as i64,
+                })
+            })
+            .collect::<Result<Vec<WalletDelta>, String>>()?;
+        db.apply_wallet_deltas(&deltas, recorded.last_height as i64);
+        info!(
+            "Added heights {}..={}, {} addresses",
+            recorded.first_height,
+            recorded.last_height,
+            deltas.len()
+        );
+    }
+    info!(
+        "Wallet history is at height {}, added {} segments",
+        next_height as i64 - 1,
+        pending.len()
+    );
+    Ok(())
+}
+
+/// What happened to every address in the blocks of a segment
+fn segment_history(data: &DataDir, recorded: &CheckpointSegment) -> Result<History, String> {
+    let segment = data.read_segment(recorded.id)?;
+    let mut history = History::new();
+    for block in segment.blocks.iter() {
+        history.add_block(block);
+    }
+    Ok(history)
+}
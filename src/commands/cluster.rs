@@ -0,0 +1,84 @@
+use crate::cli::ClusterArgs;
+use buttcoin::cluster::{
+    self, Clusterer, Clustering, CollaborativeTransactions, EqualOutputs, Exclusions, MaxInputs,
+};
+use buttcoin::reader::DataDir;
+use log::{info, warn};
+
+/// Cluster the addresses up to a height, write clusters.dat and print the largest clusters
+pub fn cluster(data: &DataDir, args: &ClusterArgs) -> Result<(), String> {
+    let height = match args.height.or_else(|| data.contiguous_height()) {
+        Some(height) => height,
+        None => return Err("No heights are ingested from genesis".to_string()),
+    };
+    let mut exclusions = Exclusions::empty();
+    if !args.merge_collaborative {
+        exclusions.add(Box::new(CollaborativeTransactions));
+    }
+    if args.equal_outputs > 0 {
+        exclusions.add(Box::new(EqualOutputs {
+            min_outputs: args.equal_outputs,
+        }));
+    }
+    if let Some(max_inputs) = args.max_inputs {
+        exclusions.add(Box::new(MaxInputs { max_inputs }));
+    }
+
+    let mut clusterer = Clusterer::new(exclusions);
+    if let Some(min_confidence) = args.change_confidence {
+        clusterer = clusterer.with_change(min_confidence);
+    }
+    let clustering = cluster::cluster(data, height, clusterer)?;
+    if clustering.unresolved > 0 {
+        warn!(
+            "{} inputs are unresolved and not merged, run resolve first",
+            clustering.unresolved
+        );
+    }
+    clustering.save(data.get_dir(), data.network()?)?;
+    info!(
+        "Wrote {} addresses in {} clusters to {}",
+        clustering.addresses.len(),
+        clustering.clusters.len(),
+        Clustering::path(data.get_dir()).display()
+    );
+    print_clustering(data, &clustering, args.top)
+}
+
+fn print_clustering(data: &DataDir, clustering: &Clustering, top: usize) -> Result<(), String> {
+    let mut largest: Vec<_> = clustering.clusters.iter().collect();
+    largest.sort_unstable_by(|a, b| b.balance.cmp(&a.balance).then(a.id.cmp(&b.id)));
+    largest.truncate(top);
+
+    // Show one address of every cluster
+    let first_addresses: Vec<u64> = largest
+        .iter()
+        .filter_map(|cluster| {
+            clustering
+                .addresses
+                .iter()
+                .find(|(_, id)| *id == cluster.id)
+                .map(|(hash, _)| *hash)
+        })
+        .collect();
+    let wallets = data.find_wallets(&first_addresses)?;
+
+    println!("Clusters at height {}", clustering.height);
+    println!("  Addresses:     {}", clustering.addresses.len());
+    println!("  Clusters:      {}", clustering.clusters.len());
+    for (name, count) in clustering.excluded.iter() {
+        println!("  Excluded:      {} {}", count, name);
+    }
+    println!("  Largest:");
+    for cluster in largest.iter() {
+        let address = wallets
+            .iter()
+            .find(|wallet| clustering.cluster_of(wallet.hash) == Some(cluster.id))
+            .map_or("unknown", |wallet| wallet.address.as_str());
+        println!(
+            "    {:>8} {:>8} {:<62} {:>20}",
+            cluster.id, cluster.addresses, address, cluster.balance
+        );
+    }
+    Ok(())
+}
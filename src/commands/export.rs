@@ -0,0 +1,112 @@
+use crate::cli::{ExportArgs, ExportKind};
+use buttcoin::reader::DataDir;
+use buttcoin::{Block, Payload};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+/***
+ * Write records of the data directory as JSON lines. Blocks and protocol usage are written in
+ * height order and can be limited to a height range, wallets are written once per address.
+ */
+pub fn export(data: &DataDir, args: &ExportArgs) -> Result<(), String> {
+    let writer: Box<dyn Write> = match &args.output {
+        Some(path) => Box::new(
+            File::create(path)
+                .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?,
+        ),
+        None => Box::new(std::io::stdout()),
+    };
+    let mut writer = BufWriter::new(writer);
+    let end_height = args.end_height.unwrap_or(u64::MAX);
+
+    match args.what {
+        ExportKind::Blocks | ExportKind::Protocols => {
+            for recorded in data.get_segments() {
+                if recorded.first_height > recorded.last_height
+                    || recorded.last_height < args.start_height
+                    || recorded.first_height >= end_height
+                {
+                    continue;
+                }
+                let segment = data.read_segment(recorded.id)?;
+                for block in segment.blocks.iter() {
+                    if !(args.start_height..end_height).contains(&block.height) {
+                        continue;
+                    }
+                    match args.what {
+                        ExportKind::Protocols => {
+                            for usage in protocol_usage(block) {
+                                write_line(&mut writer, &usage)?;
+                            }
+                        }
+                        _ => write_line(&mut writer, block)?,
+                    }
+                }
+            }
+        }
+        ExportKind::Wallets => {
+            let mut seen = hashbrown::HashSet::new();
+            for wallet in data.wallets() {
+                let wallet = wallet?;
+                if seen.insert(wallet.hash) {
+                    write_line(&mut writer, &wallet)?;
+                }
+            }
+        }
+    }
+    writer
+        .flush()
+        .map_err(|e| format!("Failed to write export: {}", e))
+}
+
+#[derive(Serialize, Default)]
+struct ProtocolUsage {
+    height: u64,
+    protocol: String,
+    // OP_RETURN outputs and the bytes they push
+    outputs: u64,
+    output_bytes: u64,
+    // Inputs with data in their witness
+    inputs: u64,
+}
+
+/// Count embedded data in a block by protocol, OP_RETURN outputs without one count as "unknown"
+fn protocol_usage(block: &Block) -> Vec<ProtocolUsage> {
+    let mut usage: BTreeMap<&str, ProtocolUsage> = BTreeMap::new();
+    for tx in block.transactions.iter() {
+        for vout in tx.vouts.iter() {
+            if let Some(Payload::Data { pushes, protocol }) = &vout.payload {
+                let entry = usage
+                    .entry(protocol.as_deref().unwrap_or("unknown"))
+                    .or_default();
+                entry.outputs += 1;
+                entry.output_bytes += pushes.iter().map(|push| push.len() as u64).sum::<u64>();
+            }
+        }
+        for vin in tx.vins.iter() {
+            if let Some(protocol) = &vin.protocol {
+                usage.entry(protocol).or_default().inputs += 1;
+            }
+        }
+    }
+    usage
+        .into_iter()
+        .map(|(protocol, usage)| ProtocolUsage {
+            height: block.height,
+            protocol: protocol.to_string(),
+            ..usage
+        })
+        .collect()
+}
+
+fn write_line<W: Write, T: Serialize>(writer: &mut W, record: &T) -> Result<(), String> {
+    serde_json::to_writer(&mut *writer, record)
+        .map_err(|e| format!("Failed to serialize record: {}", e))?;
+    writer
+        .write_all(b"\n")
+        .map_err(|e| format!("Failed to write export: {}", e))
+}
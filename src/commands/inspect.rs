@@ -0,0 +1,238 @@
+use crate::cli::InspectCommand;
+use buttcoin::reader::DataDir;
+use buttcoin::{Block, Payload, ScriptType, Transaction, Wallet};
+
+/// Print parts of the data directory
+pub fn inspect(data: &DataDir, command: &InspectCommand) -> Result<(), String> {
+    match command {
+        InspectCommand::Segment { id } => print_segment(data, *id),
+        InspectCommand::Block { block } => {
+            let found = match block.parse::<u64>() {
+                Ok(height) => data.find_block_by_height(height)?,
+                Err(_) => data.find_block_by_hash(block)?,
+            };
+            let block = found.ok_or_else(|| format!("Block {} not found", block))?;
+            print_block(&block);
+            Ok(())
+        }
+        InspectCommand::Tx { txid } => {
+            let (height, transaction) = data
+                .find_transaction(txid)?
+                .ok_or_else(|| format!("Transaction {} not found", txid))?;
+            print_transaction(data, height, &transaction)
+        }
+    }
+}
+
+fn print_segment(data: &DataDir, id: usize) -> Result<(), String> {
+    let header = data.read_header(id)?;
+    let segment = data.read_segment(id)?;
+    let wallets = data.read_wallets(id)?;
+    let nr_vins: usize = segment
+        .blocks
+        .iter()
+        .flat_map(|block| block.transactions.iter())
+        .map(|tx| tx.vins.len())
+        .sum();
+    let nr_vouts: usize = segment
+        .blocks
+        .iter()
+        .flat_map(|block| block.transactions.iter())
+        .map(|tx| tx.vouts.len())
+        .sum();
+
+    println!("Segment {}", id);
+    println!("  File:          {}", data.blocks_path(id)?.display());
+    println!("  Network:       {}", header.network);
+    println!(
+        "  Heights:       {}..={}",
+        segment.first_height, segment.last_height
+    );
+    println!("  Blocks:        {}", segment.blocks.len());
+    println!("  Transactions:  {}", header.nr_transactions);
+    println!("  Inputs:        {}", nr_vins);
+    println!("  Outputs:       {}", nr_vouts);
+    println!("  Wallets:       {}", wallets.len());
+    if let (Some(first), Some(last)) = (segment.blocks.first(), segment.blocks.last()) {
+        println!("  First block:   {}", first.hash);
+        println!("  Last block:    {}", last.hash);
+    }
+    println!("  Outputs by script type:");
+    for script_type in ScriptType::ALL.iter() {
+        let (count, value) = segment
+            .blocks
+            .iter()
+            .flat_map(|block| block.transactions.iter())
+            .flat_map(|tx| tx.vouts.iter())
+            .filter(|vout| vout.script_type == *script_type)
+            .fold((0, 0), |(count, value), vout| {
+                (count + 1, value + vout.value)
+            });
+        if count > 0 {
+            println!("    {:<22} {:>10} {:>20}", script_type, count, value);
+        }
+    }
+    Ok(())
+}
+
+fn print_block(block: &Block) {
+    println!("Block {}", block.hash);
+    println!("  Height:        {}", block.height);
+    println!("  Time:          {}", block.timestamp);
+    println!("  Transactions:  {}", block.transactions.len());
+    match &block.fees {
+        Some(fees) => {
+            println!("  Fees:          {}", fees.total);
+            println!(
+                "  Fee rates:     min {} median {} max {} sat/kvB",
+                fees.min_feerate, fees.median_feerate, fees.max_feerate
+            );
+            println!(
+                "  Fee p10..p90:  {}",
+                fees.fee_percentiles
+                    .iter()
+                    .map(|fee| fee.to_string())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            );
+        }
+        None => println!("  Fees:          unresolved"),
+    }
+    if let Some(coinbase) = &block.coinbase {
+        match coinbase.height {
+            Some(height) if height == block.height => println!("  Coinbase:      BIP34 height"),
+            Some(number) => println!("  Coinbase:      starts with {}", number),
+            None => println!("  Coinbase:      starts with no number"),
+        }
+        println!("  Extranonce:    {}", hex::encode(&coinbase.extranonce));
+        if !coinbase.tag.is_empty() {
+            println!("  Tag:           {}", coinbase.tag);
+        }
+        println!(
+            "  Claimed:       {} of subsidy {}",
+            coinbase.claimed, coinbase.subsidy
+        );
+        if let Some(under_claimed) = coinbase.under_claimed {
+            println!("  Unclaimed:     {}", under_claimed);
+        }
+    }
+    for tx in block.transactions.iter() {
+        println!(
+            "    {} inputs: {} outputs: {} value: {}",
+            tx.txid,
+            tx.vins.len(),
+            tx.vouts.len(),
+            output_value(tx)
+        );
+    }
+}
+
+fn print_transaction(data: &DataDir, height: u64, tx: &Transaction) -> Result<(), String> {
+    let mut wallet_hashes: Vec<u64> = tx.vouts.iter().filter_map(|vout| vout.wallet).collect();
+    for vout in tx.vouts.iter() {
+        if let Some(Payload::Multisig { keys, .. }) = &vout.payload {
+            wallet_hashes.extend(keys.iter().flatten());
+        }
+    }
+    wallet_hashes.extend(
+        tx.vins
+            .iter()
+            .filter_map(|vin| vin.prevout.as_ref().and_then(|prevout| prevout.wallet)),
+    );
+    let wallets = data.find_wallets(&wallet_hashes)?;
+
+    println!("Transaction {}", tx.txid);
+    println!("  Height:        {}", height);
+    println!("  Weight:        {} ({} vbytes)", tx.weight, tx.vsize());
+    if let Some(fee) = tx.fee {
+        println!(
+            "  Fee:           {} ({} sat/kvB)",
+            fee,
+            tx.feerate().unwrap_or(0)
+        );
+    }
+    println!("  Inputs:        {}", tx.vins.len());
+    for (idx, vin) in tx.vins.iter().enumerate() {
+        match &vin.protocol {
+            Some(protocol) => println!(
+                "    {}: {:016x}:{} {}",
+                idx, vin.txid_hash, vin.vout_idx, protocol
+            ),
+            None => println!("    {}: {:016x}:{}", idx, vin.txid_hash, vin.vout_idx),
+        }
+        match &vin.prevout {
+            Some(prevout) => println!(
+                "       {} {} {}",
+                prevout.script_type,
+                address(&wallets, prevout.wallet),
+                prevout.value
+            ),
+            None => println!("       unresolved"),
+        }
+    }
+    println!("  Outputs:       {}", tx.vouts.len());
+    for (idx, vout) in tx.vouts.iter().enumerate() {
+        println!(
+            "    {}: {} {} {}",
+            idx,
+            vout.script_type,
+            address(&wallets, vout.wallet),
+            vout.value
+        );
+        match &vout.payload {
+            Some(Payload::Multisig { required, keys }) => {
+                println!("       {}-of-{}", required, keys.len());
+                for key in keys.iter() {
+                    println!("       {}", address(&wallets, *key));
+                }
+            }
+            Some(Payload::PubKey { key, compressed }) => {
+                let kind = if *compressed {
+                    "compressed"
+                } else {
+                    "uncompressed"
+                };
+                println!("       {} {}", kind, hex::encode(key));
+            }
+            Some(Payload::Data { pushes, protocol }) => {
+                println!(
+                    "       {}",
+                    protocol.as_deref().unwrap_or("unknown protocol")
+                );
+                for push in pushes.iter() {
+                    println!("       {}", hex::encode(push));
+                }
+            }
+            None => {}
+        }
+    }
+    if let Some(collaborative) = tx.collaborative {
+        println!("  Collaborative: {}", collaborative);
+    }
+    if !tx.change.is_empty() {
+        println!("  Change:");
+        for label in tx.change.iter() {
+            println!(
+                "    {}: {:<15} {:>3}%",
+                label.vout_idx, label.heuristic, label.confidence
+            );
+        }
+    }
+    println!("  Value:         {}", output_value(tx));
+    Ok(())
+}
+
+fn address(wallets: &[Wallet], hash: Option<u64>) -> &str {
+    match hash {
+        Some(hash) => wallets
+            .iter()
+            .find(|wallet| wallet.hash == hash)
+            .map(|wallet| wallet.address.as_str())
+            .unwrap_or("unknown"),
+        None => "-",
+    }
+}
+
+fn output_value(tx: &Transaction) -> u64 {
+    tx.vouts.iter().map(|vout| vout.value).sum()
+}
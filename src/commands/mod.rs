@@ -0,0 +1,8 @@
+// Subcommands of the binary, one module each. Ingest, resolve and verify live in main.rs
+pub mod audit;
+pub mod balances;
+pub mod cluster;
+pub mod export;
+pub mod inspect;
+pub mod report;
+pub mod utxo;
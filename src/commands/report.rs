@@ -0,0 +1,67 @@
+use crate::cli::ReportArgs;
+use buttcoin::balance;
+use buttcoin::reader::DataDir;
+use buttcoin::richlist::RichList;
+use log::warn;
+
+/// Print the rich list and balance distribution at a height
+pub fn report(data: &DataDir, args: &ReportArgs) -> Result<(), String> {
+    let height = match args.height.or_else(|| data.contiguous_height()) {
+        Some(height) => height,
+        None => return Err("No heights are ingested from genesis".to_string()),
+    };
+    if data
+        .contiguous_height()
+        .is_none_or(|ingested| ingested < height)
+    {
+        warn!(
+            "Heights up to {} aren't all ingested, balances are incomplete",
+            height
+        );
+    }
+    let history = balance::history_at(data, height)?;
+    if history.unresolved > 0 {
+        warn!(
+            "{} inputs are unresolved and not debited, run resolve first",
+            history.unresolved
+        );
+    }
+    let richlist = RichList::new(&history, args.top);
+    let hashes: Vec<u64> = richlist.top.iter().map(|(hash, _)| *hash).collect();
+    let wallets = data.find_wallets(&hashes)?;
+
+    println!("Rich list at height {}", height);
+    println!("  Addresses:     {}", richlist.addresses);
+    println!("  Total:         {}", richlist.total);
+    println!("  Gini:          {:.4}", richlist.gini);
+    println!("  Top 1%:        {:.2}%", richlist.top_1_share * 100.0);
+    println!("  Top 10%:       {:.2}%", richlist.top_10_share * 100.0);
+    println!("  Balances:");
+    for bucket in richlist.buckets.iter() {
+        println!(
+            "    >= {:<14} {:>10} {:>20}",
+            amount(bucket.min),
+            bucket.addresses,
+            bucket.total
+        );
+    }
+    println!("  Top {}:", richlist.top.len());
+    for (rank, (hash, balance)) in richlist.top.iter().enumerate() {
+        let address = wallets
+            .iter()
+            .find(|wallet| wallet.hash == *hash)
+            .map_or("unknown", |wallet| wallet.address.as_str());
+        println!("    {:>5} {:<62} {:>20}", rank + 1, address, balance);
+    }
+    Ok(())
+}
+
+/// A power of ten of sat, in BTC from 0.01 BTC up
+fn amount(sat: u64) -> String {
+    match sat {
+        sat if sat >= 100_000_000_000 => format!("{}k BTC", sat / 100_000_000_000),
+        sat if sat >= 1_000_000 => format!("{} BTC", sat as f64 / 100_000_000.0),
+        sat if sat >= 1_000 => format!("{}k sat", sat / 1_000),
+        sat => format!("{} sat", sat),
+    }
+}
@@ -0,0 +1,80 @@
+use crate::cli::UtxoArgs;
+use crate::rpc_client;
+use bitcoin::RpcApi;
+use bitcoincore_rpc as bitcoin;
+use buttcoin::reader::DataDir;
+use buttcoin::utxo::{self, Snapshot};
+use log::info;
+use serde::Deserialize;
+
+// The totals of gettxoutsetinfo, read loosely as the hash fields differ between Core versions
+#[derive(Deserialize)]
+struct TxOutSetInfo {
+    height: u64,
+    transactions: u64,
+    txouts: u64,
+    total_amount: f64,
+}
+
+/// Build the UTXO set at a height and compare its totals with the node's when asked
+pub fn utxo_set(args: &UtxoArgs) -> Result<(), String> {
+    let data = DataDir::open(&args.data.data_dir)?;
+    let node: Option<TxOutSetInfo> = if args.check {
+        let info = rpc_client(&args.rpc)?
+            .call("gettxoutsetinfo", &[])
+            .map_err(|e| format!("Failed to get the node's UTXO set: {}", e))?;
+        Some(info)
+    } else {
+        None
+    };
+    let height = match (args.height, &node, data.contiguous_height()) {
+        (Some(height), _, _) => height,
+        (None, Some(node), _) => node.height,
+        (None, None, Some(height)) => height,
+        (None, None, None) => return Err("No heights are ingested from genesis".to_string()),
+    };
+
+    let memory_cap = args.memory_cap.map(|megabytes| megabytes * 1024 * 1024);
+    let snapshot = utxo::build(&data, height, memory_cap)?;
+    print_snapshot(&snapshot);
+
+    let node = match node {
+        Some(node) => node,
+        None => return Ok(()),
+    };
+    if node.height != snapshot.height {
+        return Err(format!(
+            "The node is at height {}, not {}",
+            node.height, snapshot.height
+        ));
+    }
+    let node_amount = bitcoin::bitcoin::Amount::from_btc(node.total_amount)
+        .map_err(|e| format!("Invalid total amount from the node: {}", e))?
+        .as_sat();
+    let mismatches: Vec<String> = [
+        ("transactions", snapshot.transactions, node.transactions),
+        ("txouts", snapshot.txouts, node.txouts),
+        ("total_amount", snapshot.total_amount, node_amount),
+    ]
+    .iter()
+    .filter(|(_, ours, theirs)| ours != theirs)
+    .map(|(name, ours, theirs)| format!("{} is {}, the node has {}", name, ours, theirs))
+    .collect();
+    if !mismatches.is_empty() {
+        return Err(format!(
+            "UTXO set differs from the node: {}",
+            mismatches.join(", ")
+        ));
+    }
+    info!("UTXO set matches the node at height {}", node.height);
+    Ok(())
+}
+
+fn print_snapshot(snapshot: &Snapshot) {
+    println!("UTXO set at height {}", snapshot.height);
+    println!("  Transactions:  {}", snapshot.transactions);
+    println!("  Outputs:       {}", snapshot.txouts);
+    println!("  Total amount:  {}", snapshot.total_amount);
+    println!("  BIP30:         {}", snapshot.bip30_overwritten);
+    println!("  Partitions:    {}", snapshot.partitions.len());
+}
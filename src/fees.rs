@@ -0,0 +1,61 @@
+use crate::{Block, BlockFees, Transaction};
+
+/***
+ * Fees paid by transactions and collected per block. A fee is the value of the inputs minus the
+ * value of the outputs, so it is only known once the resolve pass filled in every input. Fee rates
+ * are in sat per 1000 vbytes, integers so that the model stays comparable and serializable.
+ */
+// Percentiles of the fees in a block, as in BlockFees::fee_percentiles
+const PERCENTILES: [u64; 5] = [10, 25, 50, 75, 90];
+
+/// The fee of a transaction, None for a coinbase, unresolved inputs or outputs exceeding inputs
+pub fn transaction_fee(tx: &Transaction) -> Option<u64> {
+    if tx.vins.is_empty() {
+        return None;
+    }
+    let mut input_value: u64 = 0;
+    for vin in tx.vins.iter() {
+        input_value = input_value.checked_add(vin.prevout.as_ref()?.value)?;
+    }
+    let output_value = tx.vouts.iter().map(|vout| vout.value).sum();
+    input_value.checked_sub(output_value)
+}
+
+/// Fee statistics of a block from the fees of its transactions, None while any of them is unknown
+pub fn block_fees(block: &Block) -> Option<BlockFees> {
+    let mut fees = Vec::new();
+    let mut feerates = Vec::new();
+    for tx in block.transactions.iter().filter(|tx| !tx.vins.is_empty()) {
+        fees.push(tx.fee?);
+        feerates.push(tx.feerate()?);
+    }
+    if fees.is_empty() {
+        return Some(BlockFees::default());
+    }
+    fees.sort_unstable();
+    feerates.sort_unstable();
+
+    let middle = feerates.len() / 2;
+    let median_feerate = if feerates.len().is_multiple_of(2) {
+        (feerates[middle - 1] + feerates[middle]) / 2
+    } else {
+        feerates[middle]
+    };
+    let mut fee_percentiles = [0; 5];
+    for (fee, percentile) in fee_percentiles.iter_mut().zip(PERCENTILES.iter()) {
+        *fee = nearest_rank(&fees, *percentile);
+    }
+    Some(BlockFees {
+        total: fees.iter().sum(),
+        min_feerate: feerates[0],
+        median_feerate,
+        max_feerate: feerates[feerates.len() - 1],
+        fee_percentiles,
+    })
+}
+
+/// The smallest value with at least percentile percent of the sorted values at or below it
+fn nearest_rank(sorted: &[u64], percentile: u64) -> u64 {
+    let rank = (percentile * sorted.len() as u64).div_ceil(100).max(1);
+    sorted[rank as usize - 1]
+}
@@ -0,0 +1,494 @@
+use crate::checkpoint::Checkpoint;
+use crate::coinbase;
+use crate::coinjoin;
+use crate::layout::Layout;
+use crate::model::{Block, Payload, ScriptType, Segment, Transaction, Vin, Vout, Wallet};
+use crate::protocol::Taggers;
+use crate::script::{
+    data_pushes, multisig_keys, p2pk_key, pubkey_to_p2pkh, script_to_address, script_type,
+};
+use crate::segment::{self, FileHeader, FileKind};
+use crate::source::BlockSource;
+use bitcoincore_rpc as bitcoin;
+use hashbrown::HashSet;
+use log::info;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+    sync::{mpsc, Arc, Mutex, RwLock},
+    time::Instant,
+};
+
+pub const DATA_DIR: &str = "data";
+
+pub struct Context {
+    // This is global processed blocks for all thread executions, keyed by height.
+    // Blocks are moved out in height order once every height before them has been processed.
+    nr_total_blocks: u64,
+    processed_blocks: Arc<RwLock<BTreeMap<u64, Block>>>,
+    // The heights still to process in order, and the segment being filled from them
+    pending_heights: Vec<u64>,
+    segment_buffer: Arc<RwLock<SegmentBuffer>>,
+    chunk_nr: Arc<AtomicUsize>,
+    // The number of blocks to process in a chunk
+    chunk_size_in_blocks: u64,
+    // Number of transactions to flush per segment, approximately
+    segment_transactions_flush_threshold: u64,
+    nr_blocks_processed: Arc<AtomicU64>,
+    wallets: Arc<RwLock<HashSet<Wallet>>>,
+    checkpoint: Arc<Mutex<Checkpoint>>,
+    // Set on the first Ctrl-C, chunks that haven't started yet are skipped
+    shutdown: Arc<AtomicBool>,
+    network: bitcoin::bitcoin::Network,
+    data_dir: PathBuf,
+    layout: Layout,
+    // Recognise protocols of data embedded in outputs and witnesses
+    taggers: Taggers,
+}
+
+#[derive(Default)]
+struct SegmentBuffer {
+    // Index into pending_heights of the next height to move into the segment
+    cursor: usize,
+    blocks: Vec<Block>,
+    nr_transactions: u64,
+}
+
+impl Context {
+    pub fn new(
+        total_blocks: u64,
+        pending_heights: Vec<u64>,
+        segment_transactions_flush_threshold: u64,
+        chunk_size: u64,
+        checkpoint: Checkpoint,
+        network: bitcoin::bitcoin::Network,
+        data_dir: &Path,
+    ) -> Self {
+        let nr_blocks_flushed = total_blocks - pending_heights.len() as u64;
+        let layout = checkpoint.get_layout();
+        Context {
+            nr_total_blocks: total_blocks,
+            processed_blocks: Arc::new(RwLock::new(BTreeMap::new())),
+            pending_heights,
+            segment_buffer: Arc::new(RwLock::new(SegmentBuffer::default())),
+            chunk_nr: Arc::new(AtomicUsize::new(checkpoint.next_segment_id())),
+            chunk_size_in_blocks: chunk_size,
+            segment_transactions_flush_threshold,
+            nr_blocks_processed: Arc::new(AtomicU64::new(nr_blocks_flushed)),
+            wallets: Arc::new(RwLock::new(HashSet::new())),
+            checkpoint: Arc::new(Mutex::new(checkpoint)),
+            shutdown: Arc::new(AtomicBool::new(false)),
+            network,
+            data_dir: data_dir.to_path_buf(),
+            layout,
+            taggers: Taggers::default(),
+        }
+    }
+
+    /// Replace the built in protocol taggers
+    pub fn with_taggers(mut self, taggers: Taggers) -> Self {
+        self.taggers = taggers;
+        self
+    }
+
+    /// Skip chunks that haven't started yet and stop after the ones in flight
+    pub fn request_shutdown(&self) {
+        self.shutdown.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown.load(Ordering::SeqCst)
+    }
+
+    pub fn get_total_blocks(&self) -> u64 {
+        self.nr_total_blocks
+    }
+
+    pub fn get_nr_blocks_processed(&self) -> u64 {
+        self.nr_blocks_processed.load(Ordering::SeqCst)
+    }
+
+    pub fn get_chunk_size(&self) -> u64 {
+        self.chunk_size_in_blocks
+    }
+
+    pub fn get_hash_for_wallet_address(&self, address: String) -> u64 {
+        let wallet_hash = xxhash_rust::const_xxh3::xxh3_64(address.as_bytes());
+        let wallet = Wallet::new(wallet_hash, address);
+        self.wallets.write().unwrap().insert(wallet);
+        wallet_hash
+    }
+
+    /***
+     * Add a processed chunk and return the segments that became complete. Chunks finish in any
+     * order, so blocks wait in processed_blocks until all lower heights are there and are then
+     * moved into the segment buffer in height order. A segment is cut when it reaches the
+     * transaction threshold, at the last height, or where a previous run already flushed the
+     * following heights, so every segment holds a contiguous height range.
+     */
+    fn add_blocks_and_flush(&self, processed_blocks: Vec<Block>) -> Vec<Segment> {
+        let mut flush: Vec<Segment> = Vec::new();
+        self.nr_blocks_processed
+            .fetch_add(processed_blocks.len() as u64, Ordering::SeqCst);
+
+        // Hold the locks for as briefly as possible, only to move blocks and produce the segments.
+        let mut processed_blocks_global = self.processed_blocks.write().unwrap();
+        processed_blocks_global.extend(
+            processed_blocks
+                .into_iter()
+                .map(|block| (block.height, block)),
+        );
+
+        let mut buffer = self.segment_buffer.write().unwrap();
+        while let Some(height) = self.pending_heights.get(buffer.cursor) {
+            let block = match processed_blocks_global.remove(height) {
+                Some(block) => block,
+                None => break,
+            };
+            buffer.cursor += 1;
+            buffer.nr_transactions += block.transactions.len() as u64;
+            buffer.blocks.push(block);
+
+            let next_height = self.pending_heights.get(buffer.cursor);
+            if buffer.nr_transactions >= self.segment_transactions_flush_threshold
+                || next_height != Some(&(height + 1))
+            {
+                flush.push(self.new_segment(buffer.blocks.drain(0..).collect()));
+                buffer.nr_transactions = 0;
+            }
+        }
+
+        flush
+    }
+
+    /***
+     * Drain whatever blocks have not been flushed into final segments after an interrupt. Blocks
+     * that were waiting on a lower height that never got processed go out as their own segments,
+     * one per contiguous height range.
+     */
+    fn flush_remaining(&self) -> Vec<Segment> {
+        let mut processed_blocks_global = self.processed_blocks.write().unwrap();
+        let mut buffer = self.segment_buffer.write().unwrap();
+
+        let mut flush: Vec<Segment> = Vec::new();
+        if !buffer.blocks.is_empty() {
+            flush.push(self.new_segment(buffer.blocks.drain(0..).collect()));
+            buffer.nr_transactions = 0;
+        }
+
+        let mut blocks: Vec<Block> = Vec::new();
+        for (height, block) in std::mem::take(&mut *processed_blocks_global) {
+            if let Some(last) = blocks.last() {
+                if last.height + 1 != height {
+                    flush.push(self.new_segment(std::mem::take(&mut blocks)));
+                }
+            }
+            blocks.push(block);
+        }
+        if !blocks.is_empty() {
+            flush.push(self.new_segment(blocks));
+        }
+
+        // Wallets without blocks still need a segment to be written under
+        if flush.is_empty() && !self.wallets.read().unwrap().is_empty() {
+            flush.push(self.new_segment(Vec::new()));
+        }
+        flush
+    }
+
+    /// Number a segment of height ordered, contiguous blocks
+    fn new_segment(&self, blocks: Vec<Block>) -> Segment {
+        let chunknr = self.chunk_nr.fetch_add(1, Ordering::SeqCst);
+        Segment::new(chunknr, blocks)
+    }
+
+    /// Record a segment whose files are on disk so a restart skips its blocks
+    fn checkpoint_segment(&self, segment: &Segment) -> Result<(), String> {
+        let mut checkpoint = self
+            .checkpoint
+            .lock()
+            .map_err(|e| format!("Failed to lock the checkpoint: {}", e))?;
+        checkpoint.add_segment(segment.id, segment.first_height, segment.last_height);
+        checkpoint.save(&self.data_dir)
+    }
+}
+
+/***
+ * Spawn a task per chunk of blocknums. A chunk that fails sends its error on errors and requests a
+ * shutdown, so the chunks after it are skipped the same way as after a Ctrl-C.
+ */
+pub fn with_scope<'a>(
+    scope: &rayon::ScopeFifo<'a>,
+    source: &'a dyn BlockSource,
+    blocknums: &'a [u64],
+    ctx: Arc<Context>,
+    errors: mpsc::Sender<String>,
+) {
+    blocknums
+        .chunks(ctx.get_chunk_size() as usize)
+        .for_each(|chunk| {
+            let ctx = ctx.clone();
+            let errors = errors.clone();
+
+            scope.spawn_fifo(move |_| {
+                if ctx.is_shutting_down() {
+                    return;
+                }
+                if let Err(e) = on_chunk(&ctx, source, chunk) {
+                    ctx.request_shutdown();
+                    // The receiver outlives the scope, so this can't fail
+                    let _ = errors.send(e);
+                }
+            });
+        });
+}
+
+fn on_chunk(ctx: &Arc<Context>, source: &dyn BlockSource, chunk: &[u64]) -> Result<(), String> {
+    /***
+     * Fetch chunk
+     */
+    let mut bitcoin_blocks: Vec<(u64, bitcoincore_rpc::bitcoin::Block)> = Vec::new();
+    let mut processed_transactions = 0;
+    // This is local to every thread execution
+    let mut processed_blocks_local: Vec<Block> = Vec::new();
+
+    let start_fetch = Instant::now();
+    for blocknum in chunk.iter() {
+        let block = source.get_block_by_height(*blocknum)?;
+        bitcoin_blocks.push((*blocknum, block));
+    }
+    let end_fetch = Instant::now().duration_since(start_fetch);
+
+    /***
+     * Process chunk
+     **/
+    let start_process = Instant::now();
+    bitcoin_blocks.iter().for_each(|(height, block)| {
+        let block = on_block(ctx.clone(), *height, block);
+        processed_transactions += block.transactions.len();
+        processed_blocks_local.push(block);
+    });
+    let end_process = Instant::now().duration_since(start_process);
+
+    /***
+     * Now store to disk, but acquire write lock briefly and perform the flush later so
+     * we don't hold the lock.
+     **/
+    let start_flush = Instant::now();
+    let segments: Vec<Segment> = ctx.add_blocks_and_flush(processed_blocks_local);
+    for segment in segments.iter() {
+        write_blocks(ctx, segment)?;
+    }
+    let end_flush = Instant::now().duration_since(start_flush);
+
+    /***
+     * Flush wallets using the same segment ID
+     **/
+    let start_wallets = Instant::now();
+    let mut flushed_wallets = 0;
+    for segment in segments.iter() {
+        flushed_wallets += write_wallets(ctx, segment)?;
+    }
+    let end_wallets = Instant::now().duration_since(start_wallets);
+
+    info!(
+        "Processed blocks {}/{}; Transactions: {}; Fetch: {}ms; Process: {}ms; Flush: {}ms; Wallets ({}): {}ms",
+        ctx.get_nr_blocks_processed(),
+        ctx.get_total_blocks(),
+        processed_transactions,
+        end_fetch.as_millis(),
+        end_process.as_millis(),
+        end_flush.as_millis(),
+        flushed_wallets,
+        end_wallets.as_millis(),
+    );
+    Ok(())
+}
+
+fn segment_header(
+    ctx: &Context,
+    kind: FileKind,
+    segment: &Segment,
+    nr_records: usize,
+) -> FileHeader {
+    FileHeader {
+        kind,
+        network: ctx.network.to_string(),
+        segment_id: segment.id,
+        first_height: segment.first_height,
+        last_height: segment.last_height,
+        nr_records: nr_records as u64,
+        nr_transactions: segment
+            .blocks
+            .iter()
+            .map(|block| block.transactions.len() as u64)
+            .sum(),
+        body_length: 0,
+    }
+}
+
+fn write_blocks(ctx: &Context, segment: &Segment) -> Result<(), String> {
+    let header = segment_header(ctx, FileKind::Blocks, segment, segment.blocks.len());
+    let path = ctx
+        .layout
+        .blocks_path(&ctx.data_dir, segment.id, segment.first_height);
+    create_segment_dir(&path)?;
+    segment::write_file(&path, header, segment)
+}
+
+/// Flush the wallets under the segment's ID and checkpoint the segment, returns the nr of wallets
+fn write_wallets(ctx: &Context, segment: &Segment) -> Result<usize, String> {
+    let wallets: Vec<Wallet> = ctx.wallets.write().unwrap().drain().collect();
+    let header = segment_header(ctx, FileKind::Wallets, segment, wallets.len());
+    let path = ctx
+        .layout
+        .wallets_path(&ctx.data_dir, segment.id, segment.first_height);
+    create_segment_dir(&path)?;
+    segment::write_file(&path, header, &wallets)?;
+
+    // Both files are synced, the segment can be skipped from now on
+    ctx.checkpoint_segment(segment)?;
+    Ok(wallets.len())
+}
+
+/// The shard directory of a segment is created by whichever segment goes into it first
+fn create_segment_dir(path: &Path) -> Result<(), String> {
+    match path.parent() {
+        Some(dir) => std::fs::create_dir_all(dir)
+            .map_err(|e| format!("Failed to create {}: {}", dir.display(), e)),
+        None => Ok(()),
+    }
+}
+
+/// Only resume on top of segments written with the same format version and network
+pub fn check_compatible(
+    data_dir: &Path,
+    checkpoint: &Checkpoint,
+    network: bitcoin::bitcoin::Network,
+) -> Result<(), String> {
+    let layout = checkpoint.get_layout();
+    for recorded in checkpoint.get_segments() {
+        let path = layout.blocks_path(data_dir, recorded.id, recorded.first_height);
+        let header = segment::read_header(&path)?;
+        if header.network != network.to_string() {
+            return Err(format!(
+                "segment {} was written for {}, not {}",
+                recorded.id, header.network, network
+            ));
+        }
+    }
+    Ok(())
+}
+
+/***
+ * Process blocknums from the source on the pool until done, until a shutdown is requested or until
+ * a chunk fails, then flush whatever is left. Returns the first error of a failed chunk once the
+ * blocks processed before it are on disk.
+ */
+pub fn run(
+    pool: &rayon::ThreadPool,
+    source: &dyn BlockSource,
+    blocknums: &[u64],
+    ctx: Arc<Context>,
+) -> Result<(), String> {
+    let (errors, failures) = mpsc::channel();
+    // FIFO so chunks complete roughly in height order and few blocks wait for a lower height
+    pool.scope_fifo(|scope| {
+        with_scope(scope, source, blocknums, ctx.clone(), errors);
+    });
+
+    // Only left over after an interrupt or a failure, a complete run flushes everything from the
+    // last chunk
+    for segment in ctx.flush_remaining() {
+        write_blocks(&ctx, &segment)?;
+        let flushed_wallets = write_wallets(&ctx, &segment)?;
+        info!(
+            "Flushed final segment {} with {} blocks and {} wallets",
+            segment.id,
+            segment.blocks.len(),
+            flushed_wallets
+        );
+    }
+
+    match failures.try_iter().next() {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+pub fn on_block(ctx: Arc<Context>, height: u64, block: &bitcoincore_rpc::bitcoin::Block) -> Block {
+    let mut block_result = Block::new(height, block.block_hash().to_string(), block.header.time);
+    let txdata = &block.txdata;
+    block_result.coinbase = txdata
+        .first()
+        .map(|tx| coinbase::parse(tx, ctx.network, height));
+    for tx in txdata {
+        let transaction = on_transaction(ctx.clone(), tx);
+        block_result.add_transaction(transaction);
+    }
+
+    block_result
+}
+
+pub fn on_transaction(
+    ctx: Arc<Context>,
+    tx: &bitcoincore_rpc::bitcoin::Transaction,
+) -> Transaction {
+    let txid = tx.txid().to_string();
+    let hash = xxhash_rust::const_xxh3::xxh3_64(txid.as_bytes());
+    let mut transaction = Transaction::new(hash, txid);
+    transaction.weight = tx.get_weight() as u64;
+
+    // Don't store coinbase transactions as they all originate from the aether and not an input wallet
+    if !tx.is_coin_base() {
+        for (vin_idx, input) in tx.input.iter().enumerate() {
+            let prev_out = input.previous_output;
+            let txid = prev_out.txid.to_string();
+            let hash = xxhash_rust::const_xxh3::xxh3_64(txid.as_bytes());
+            let vout_idx = prev_out.vout;
+            let mut vin = Vin::new(hash, vout_idx);
+            vin.protocol = ctx.taggers.tag_witness(tx, vin_idx);
+            transaction.add_vin(vin);
+        }
+    }
+
+    for (vout_idx, output) in tx.output.iter().enumerate() {
+        let script_type = script_type(&output.script_pubkey);
+        let wallet = script_to_address(&output.script_pubkey, ctx.network)
+            .ok()
+            .map(|address| ctx.get_hash_for_wallet_address(address));
+        let payload = match script_type {
+            ScriptType::P2PK => p2pk_key(&output.script_pubkey).map(|key| Payload::PubKey {
+                key: key.to_vec(),
+                compressed: key.len() == 33,
+            }),
+            ScriptType::Multisig => multisig_payload(&ctx, &output.script_pubkey),
+            ScriptType::OpReturn => {
+                let pushes = data_pushes(&output.script_pubkey);
+                let protocol = ctx.taggers.tag_data(tx, vout_idx, &pushes);
+                Some(Payload::Data { pushes, protocol })
+            }
+            _ => None,
+        };
+        transaction.add_vout(Vout::new(output.value, script_type, wallet, payload));
+    }
+
+    // PayJoins need the input values and are only found by the resolve pass
+    transaction.collaborative = coinjoin::detect(&transaction);
+    transaction
+}
+
+/// Record every key of a bare multisig by the wallet of its P2PKH address
+fn multisig_payload(ctx: &Context, script: &bitcoincore_rpc::bitcoin::Script) -> Option<Payload> {
+    let (required, keys) = multisig_keys(script)?;
+    let keys = keys
+        .iter()
+        .map(|key| {
+            pubkey_to_p2pkh(key, ctx.network)
+                .ok()
+                .map(|address| ctx.get_hash_for_wallet_address(address))
+        })
+        .collect();
+    Some(Payload::Multisig { required, keys })
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/***
+ * Where segment files are placed in the data directory. Flat keeps every file in the directory
+ * itself, Sharded groups them in subdirectories per shard of heights, named after the first height
+ * of the shard, so a full mainnet run doesn't put thousands of files in one directory:
+ *
+ *   data/0000000/blocks-0.dat
+ *   data/0100000/blocks-12.dat
+ *
+ * A segment goes into the shard of its first height. Segments that only hold wallets cover no
+ * heights and go into the first shard.
+ */
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum Layout {
+    #[default]
+    Flat,
+    // Heights per subdirectory
+    Sharded(u64),
+}
+
+impl Layout {
+    pub fn sharded(shard_size: u64) -> Result<Self, String> {
+        if shard_size == 0 {
+            return Err("Shard size has to be at least 1 height".to_string());
+        }
+        Ok(Layout::Sharded(shard_size))
+    }
+
+    pub fn segment_dir(&self, data_dir: &Path, first_height: u64) -> PathBuf {
+        match self {
+            Layout::Flat => data_dir.to_path_buf(),
+            Layout::Sharded(size) => data_dir.join(format!("{:07}", first_height / size * size)),
+        }
+    }
+
+    pub fn blocks_path(&self, data_dir: &Path, id: usize, first_height: u64) -> PathBuf {
+        self.segment_dir(data_dir, first_height)
+            .join(format!("blocks-{}.dat", id))
+    }
+
+    pub fn wallets_path(&self, data_dir: &Path, id: usize, first_height: u64) -> PathBuf {
+        self.segment_dir(data_dir, first_height)
+            .join(format!("wallets-{}.dat", id))
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layout::Flat => write!(f, "flat"),
+            Layout::Sharded(size) => write!(f, "sharded per {} heights", size),
+        }
+    }
+}
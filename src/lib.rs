@@ -0,0 +1,30 @@
+/***
+ * Parses the bitcoin blockchain into height ordered segment files of blocks, transactions and
+ * wallets. The binary drives ingestion, everything it uses is exported here for other tools.
+ */
+pub mod balance;
+pub mod blkfile;
+pub mod change;
+pub mod checkpoint;
+pub mod cluster;
+pub mod coinbase;
+pub mod coinjoin;
+pub mod fees;
+pub mod ingest;
+pub mod layout;
+pub mod model;
+pub mod network;
+pub mod protocol;
+pub mod reader;
+pub mod resolve;
+pub mod richlist;
+pub mod script;
+pub mod segment;
+pub mod source;
+pub mod supply;
+pub mod utxo;
+
+pub use model::{
+    Block, BlockFees, ChangeLabel, Coinbase, Collaborative, Heuristic, Payload, Prevout,
+    ScriptType, Segment, Transaction, Vin, Vout, Wallet,
+};
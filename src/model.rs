@@ -0,0 +1,353 @@
+// The records written to the segment files, see segment::FORMAT_VERSION when changing them
+use serde::{Deserialize, Serialize};
+
+#[derive(Eq, PartialEq, Serialize, Deserialize)]
+pub struct Wallet {
+    pub hash: u64,
+    pub address: String,
+}
+impl Wallet {
+    pub fn new(hash: u64, address: String) -> Self {
+        Wallet { hash, address }
+    }
+}
+// Don't hash the ID of the wallet, the address is a unique identifier.
+impl std::hash::Hash for Wallet {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.address.hash(state);
+    }
+}
+
+/// Kind of output script, named after Bitcoin Core's script types in Display
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum ScriptType {
+    P2PK,
+    P2PKH,
+    P2SH,
+    P2WPKH,
+    P2WSH,
+    P2TR,
+    // Bare m-of-n OP_CHECKMULTISIG
+    Multisig,
+    OpReturn,
+    // Witness programs of a version or length that isn't defined yet
+    WitnessUnknown,
+    NonStandard,
+}
+
+impl ScriptType {
+    pub const ALL: [ScriptType; 10] = [
+        ScriptType::P2PK,
+        ScriptType::P2PKH,
+        ScriptType::P2SH,
+        ScriptType::P2WPKH,
+        ScriptType::P2WSH,
+        ScriptType::P2TR,
+        ScriptType::Multisig,
+        ScriptType::OpReturn,
+        ScriptType::WitnessUnknown,
+        ScriptType::NonStandard,
+    ];
+}
+
+impl std::fmt::Display for ScriptType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ScriptType::P2PK => "pubkey",
+            ScriptType::P2PKH => "pubkeyhash",
+            ScriptType::P2SH => "scripthash",
+            ScriptType::P2WPKH => "witness_v0_keyhash",
+            ScriptType::P2WSH => "witness_v0_scripthash",
+            ScriptType::P2TR => "witness_v1_taproot",
+            ScriptType::Multisig => "multisig",
+            ScriptType::OpReturn => "nulldata",
+            ScriptType::WitnessUnknown => "witness_unknown",
+            ScriptType::NonStandard => "nonstandard",
+        };
+        f.pad(name)
+    }
+}
+
+/// What an output script holds beyond the address it pays to
+#[derive(Eq, PartialEq, Serialize, Deserialize)]
+pub enum Payload {
+    /***
+     * Bare m-of-n multisig. Every key is identified by the hash of its P2PKH address, the same
+     * wallet a P2PK or P2PKH output to that key is credited to, None for keys that aren't valid
+     * curve points.
+     */
+    Multisig {
+        required: u8,
+        keys: Vec<Option<u64>>,
+    },
+    /***
+     * The public key of a P2PK output. The output is credited to the wallet of the key's P2PKH
+     * address, this keeps the key itself and tells it apart from a real P2PKH output.
+     */
+    PubKey {
+        key: Vec<u8>,
+        compressed: bool,
+    },
+    // Pushes of an OP_RETURN output and the protocol they were tagged with
+    Data {
+        pushes: Vec<Vec<u8>>,
+        protocol: Option<String>,
+    },
+}
+
+#[derive(Eq, PartialEq, Serialize, Deserialize)]
+pub struct Vout {
+    pub value: u64,
+    pub script_type: ScriptType,
+    // Hash of the address paid to, None for scripts without an address
+    pub wallet: Option<u64>,
+    pub payload: Option<Payload>,
+}
+impl Vout {
+    pub fn new(
+        value: u64,
+        script_type: ScriptType,
+        wallet: Option<u64>,
+        payload: Option<Payload>,
+    ) -> Self {
+        Vout {
+            value,
+            script_type,
+            wallet,
+            payload,
+        }
+    }
+
+    /// Pay to pubkey, credited to the P2PKH address of the key but not a P2PKH output
+    pub fn is_p2pk(&self) -> bool {
+        self.script_type == ScriptType::P2PK
+    }
+}
+
+/// The output an input spends, filled in by the resolve pass
+#[derive(Eq, PartialEq, Clone, Serialize, Deserialize)]
+pub struct Prevout {
+    pub value: u64,
+    pub script_type: ScriptType,
+    pub wallet: Option<u64>,
+}
+impl Prevout {
+    pub fn new(vout: &Vout) -> Self {
+        Prevout {
+            value: vout.value,
+            script_type: vout.script_type,
+            wallet: vout.wallet,
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Serialize, Deserialize)]
+pub struct Vin {
+    pub txid_hash: u64,
+    pub vout_idx: u32,
+    // Protocol of data embedded in the witness, e.g. an ordinals envelope
+    pub protocol: Option<String>,
+    // None until the resolve pass found the output that is spent
+    pub prevout: Option<Prevout>,
+}
+impl Vin {
+    pub fn new(txid_hash: u64, vout_idx: u32) -> Self {
+        Vin {
+            txid_hash,
+            vout_idx,
+            protocol: None,
+            prevout: None,
+        }
+    }
+}
+
+/// The heuristics that pick the change output of a transaction, see change.rs
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Heuristic {
+    NewAddress,
+    RoundAmount,
+    ScriptType,
+    OptimalChange,
+}
+
+impl std::fmt::Display for Heuristic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Heuristic::NewAddress => "new-address",
+            Heuristic::RoundAmount => "round-amount",
+            Heuristic::ScriptType => "script-type",
+            Heuristic::OptimalChange => "optimal-change",
+        };
+        f.pad(name)
+    }
+}
+
+/// An output a heuristic takes for change, with its confidence in percent
+#[derive(Eq, PartialEq, Clone, Debug, Serialize, Deserialize)]
+pub struct ChangeLabel {
+    pub vout_idx: u32,
+    pub heuristic: Heuristic,
+    pub confidence: u8,
+}
+
+/// Transactions combining the inputs of several owners, see coinjoin.rs
+#[derive(Eq, PartialEq, Hash, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Collaborative {
+    Wasabi,
+    Whirlpool,
+    JoinMarket,
+    // Equal outputs of a coordinator that isn't recognised
+    CoinJoin,
+    // Only a candidate, a PayJoin is built to look like a regular payment
+    PayJoin,
+}
+
+impl std::fmt::Display for Collaborative {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Collaborative::Wasabi => "wasabi",
+            Collaborative::Whirlpool => "whirlpool",
+            Collaborative::JoinMarket => "joinmarket",
+            Collaborative::CoinJoin => "coinjoin",
+            Collaborative::PayJoin => "payjoin",
+        };
+        f.pad(name)
+    }
+}
+
+#[derive(Eq, PartialEq, Serialize, Deserialize)]
+pub struct Transaction {
+    pub hash: u64,
+    pub txid: String,
+    pub vins: Vec<Vin>,
+    pub vouts: Vec<Vout>,
+    // BIP141 weight of the serialized transaction, witness bytes count once and others four times
+    pub weight: u64,
+    // Inputs minus outputs, None for a coinbase or until the resolve pass knows every input
+    pub fee: Option<u64>,
+    // Filled in by the resolve pass, heuristics can disagree on the output
+    pub change: Vec<ChangeLabel>,
+    pub collaborative: Option<Collaborative>,
+}
+impl Transaction {
+    pub fn new(hash: u64, txid: String) -> Self {
+        Transaction {
+            hash,
+            txid,
+            vins: Vec::new(),
+            vouts: Vec::new(),
+            weight: 0,
+            fee: None,
+            change: Vec::new(),
+            collaborative: None,
+        }
+    }
+
+    /// Virtual size in vbytes, the weight divided by four and rounded up
+    pub fn vsize(&self) -> u64 {
+        self.weight.div_ceil(4)
+    }
+
+    /// Fee rate in sat per 1000 vbytes, the unit Bitcoin Core uses
+    pub fn feerate(&self) -> Option<u64> {
+        match self.vsize() {
+            0 => None,
+            vsize => self.fee.map(|fee| fee * 1000 / vsize),
+        }
+    }
+
+    pub fn add_vout(&mut self, vout: Vout) {
+        self.vouts.push(vout);
+    }
+
+    pub fn add_vin(&mut self, vin: Vin) {
+        self.vins.push(vin);
+    }
+}
+impl std::hash::Hash for Transaction {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.txid.hash(state);
+    }
+}
+
+/// Fees of the transactions in a block besides the coinbase, fee rates in sat per 1000 vbytes
+#[derive(Eq, PartialEq, Clone, Default, Debug, Serialize, Deserialize)]
+pub struct BlockFees {
+    pub total: u64,
+    pub min_feerate: u64,
+    pub median_feerate: u64,
+    pub max_feerate: u64,
+    // Fees at the 10th, 25th, 50th, 75th and 90th percentile
+    pub fee_percentiles: [u64; 5],
+}
+
+/// What the coinbase of a block says about its miner and claims, amounts in sat
+#[derive(Eq, PartialEq, Clone, Default, Debug, Serialize, Deserialize)]
+pub struct Coinbase {
+    // Number pushed first by the coinbase script, the height since BIP34
+    pub height: Option<u64>,
+    // The push after the height, where miners roll the extranonce
+    pub extranonce: Vec<u8>,
+    // Printable text in the coinbase script, e.g. the pool name
+    pub tag: String,
+    // Subsidy of the height by the schedule of the network
+    pub subsidy: u64,
+    // Paid by the coinbase outputs, at most the subsidy plus the fees of the block
+    pub claimed: u64,
+    // Subsidy plus fees not claimed, filled in by the resolve pass once the fees are known
+    pub under_claimed: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Block {
+    pub height: u64,
+    pub timestamp: u32,
+    pub hash: String,
+    pub transactions: Vec<Transaction>,
+    // Filled in by the resolve pass once the fee of every transaction is known
+    pub fees: Option<BlockFees>,
+    pub coinbase: Option<Coinbase>,
+}
+
+impl Block {
+    pub fn new(height: u64, hash: String, timestamp: u32) -> Self {
+        Block {
+            height,
+            timestamp,
+            hash,
+            transactions: Vec::new(),
+            fees: None,
+            coinbase: None,
+        }
+    }
+
+    pub fn add_transaction(&mut self, transaction: Transaction) {
+        self.transactions.push(transaction);
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Segment {
+    pub id: usize,
+    // Inclusive height range, blocks are ordered by height without gaps
+    pub first_height: u64,
+    pub last_height: u64,
+    pub blocks: Vec<Block>,
+}
+
+impl Segment {
+    pub fn new(id: usize, blocks: Vec<Block>) -> Self {
+        // An empty segment only carries wallets, it covers no heights
+        let (first_height, last_height) = match (blocks.first(), blocks.last()) {
+            (Some(first), Some(last)) => (first.height, last.height),
+            _ => (1, 0),
+        };
+        Segment {
+            id,
+            first_height,
+            last_height,
+            blocks,
+        }
+    }
+}
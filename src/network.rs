@@ -0,0 +1,68 @@
+use bitcoincore_rpc::bitcoin::{blockdata::constants::genesis_block, BlockHash, Network};
+
+pub const NETWORKS: [Network; 4] = [
+    Network::Bitcoin,
+    Network::Testnet,
+    Network::Signet,
+    Network::Regtest,
+];
+
+/// Map the chain name of getblockchaininfo, "main", "test", "signet" or "regtest"
+pub fn from_chain_name(chain: &str) -> Result<Network, String> {
+    match chain {
+        "main" => Ok(Network::Bitcoin),
+        "test" => Ok(Network::Testnet),
+        "signet" => Ok(Network::Signet),
+        "regtest" => Ok(Network::Regtest),
+        _ => Err(format!("Unknown chain {}", chain)),
+    }
+}
+
+/// The network whose genesis block has this hash, None for a custom chain
+pub fn from_genesis_hash(hash: &BlockHash) -> Option<Network> {
+    NETWORKS
+        .iter()
+        .copied()
+        .find(|network| genesis_block(*network).block_hash() == *hash)
+}
+
+// Bitcoin Core's COIN, the subsidy starts at 50 of them
+const COIN: u64 = 100_000_000;
+const INITIAL_SUBSIDY: u64 = 50 * COIN;
+
+/// Blocks between two halvings of the subsidy, regtest halves every 150 blocks
+pub fn halving_interval(network: Network) -> u64 {
+    match network {
+        Network::Regtest => 150,
+        _ => 210_000,
+    }
+}
+
+/// The subsidy a block at this height may claim besides the fees, as in GetBlockSubsidy
+pub fn subsidy(network: Network, height: u64) -> u64 {
+    let halvings = height / halving_interval(network);
+    if halvings >= 64 {
+        0
+    } else {
+        INITIAL_SUBSIDY >> halvings
+    }
+}
+
+/// The sum of the subsidies of every block up to and including this height
+pub fn cumulative_subsidy(network: Network, height: u64) -> u64 {
+    let interval = halving_interval(network);
+    let halvings = height / interval;
+    let full_eras: u64 = (0..halvings.min(64))
+        .map(|era| (INITIAL_SUBSIDY >> era) * interval)
+        .sum();
+    full_eras + subsidy(network, height) * (height % interval + 1)
+}
+
+/// The first height whose coinbase has to start with its height (BIP34)
+pub fn bip34_height(network: Network) -> u64 {
+    match network {
+        Network::Bitcoin => 227_931,
+        Network::Testnet => 21_111,
+        Network::Signet | Network::Regtest => 1,
+    }
+}
@@ -0,0 +1,170 @@
+use bitcoincore_rpc::bitcoin::{hashes::Hash, Transaction};
+
+/***
+ * Recognises the protocol that embedded data in a transaction belongs to. Taggers look at the
+ * data pushes of OP_RETURN outputs, at the witness of inputs, or both, and the name of the first
+ * tagger that matches is stored with the output or input.
+ *
+ * New protocols are added by implementing Tagger and adding it to the Taggers of the ingestion
+ * context, the data model doesn't need to change for them.
+ */
+pub trait Tagger: Send + Sync {
+    /// Stored with every output and input the tagger recognises
+    fn name(&self) -> &'static str;
+
+    /// Whether the pushes of the OP_RETURN output at vout_idx belong to the protocol
+    fn tag_data(&self, _tx: &Transaction, _vout_idx: usize, _pushes: &[Vec<u8>]) -> bool {
+        false
+    }
+
+    /// Whether the witness of the input at vin_idx carries data of the protocol
+    fn tag_witness(&self, _tx: &Transaction, _vin_idx: usize) -> bool {
+        false
+    }
+}
+
+pub struct Taggers {
+    taggers: Vec<Box<dyn Tagger>>,
+}
+
+impl Default for Taggers {
+    /// The built in taggers
+    fn default() -> Self {
+        Taggers {
+            taggers: vec![
+                Box::new(Omni),
+                Box::new(Counterparty),
+                Box::new(Ordinals),
+                Box::new(HashCommitment),
+            ],
+        }
+    }
+}
+
+impl Taggers {
+    pub fn empty() -> Self {
+        Taggers {
+            taggers: Vec::new(),
+        }
+    }
+
+    /// Taggers are tried in the order they were added
+    pub fn add(&mut self, tagger: Box<dyn Tagger>) {
+        self.taggers.push(tagger);
+    }
+
+    pub fn tag_data(
+        &self,
+        tx: &Transaction,
+        vout_idx: usize,
+        pushes: &[Vec<u8>],
+    ) -> Option<String> {
+        self.taggers
+            .iter()
+            .find(|tagger| tagger.tag_data(tx, vout_idx, pushes))
+            .map(|tagger| tagger.name().to_string())
+    }
+
+    pub fn tag_witness(&self, tx: &Transaction, vin_idx: usize) -> Option<String> {
+        self.taggers
+            .iter()
+            .find(|tagger| tagger.tag_witness(tx, vin_idx))
+            .map(|tagger| tagger.name().to_string())
+    }
+}
+
+/// Omni layer class C transactions, the OP_RETURN payload starts with "omni"
+pub struct Omni;
+
+impl Tagger for Omni {
+    fn name(&self) -> &'static str {
+        "omni"
+    }
+
+    fn tag_data(&self, _tx: &Transaction, _vout_idx: usize, pushes: &[Vec<u8>]) -> bool {
+        pushes.first().is_some_and(|push| push.starts_with(b"omni"))
+    }
+}
+
+/***
+ * Counterparty OP_RETURN payloads are ARC4 encrypted with the txid of the first input as key and
+ * start with "CNTRPRTY" once decrypted. The older multisig encoding isn't recognised.
+ */
+pub struct Counterparty;
+
+const COUNTERPARTY_PREFIX: &[u8] = b"CNTRPRTY";
+
+impl Tagger for Counterparty {
+    fn name(&self) -> &'static str {
+        "counterparty"
+    }
+
+    fn tag_data(&self, tx: &Transaction, _vout_idx: usize, pushes: &[Vec<u8>]) -> bool {
+        let (input, push) = match (tx.input.first(), pushes.first()) {
+            (Some(input), Some(push)) if push.len() >= COUNTERPARTY_PREFIX.len() => (input, push),
+            _ => return false,
+        };
+        // The key is the txid as it is displayed, reversed from the internal byte order
+        let mut key = input.previous_output.txid.into_inner().to_vec();
+        key.reverse();
+        arc4(&key, &push[..COUNTERPARTY_PREFIX.len()]) == COUNTERPARTY_PREFIX
+    }
+}
+
+/***
+ * Ordinals inscriptions, an envelope of OP_FALSE OP_IF "ord" ... OP_ENDIF in the tapscript of a
+ * script path spend. Anything that reuses the envelope with the "ord" tag is counted too.
+ */
+pub struct Ordinals;
+
+const ORDINALS_ENVELOPE: &[u8] = &[0x00, 0x63, 0x03, b'o', b'r', b'd'];
+
+impl Tagger for Ordinals {
+    fn name(&self) -> &'static str {
+        "ordinals"
+    }
+
+    fn tag_witness(&self, tx: &Transaction, vin_idx: usize) -> bool {
+        tx.input[vin_idx].witness.iter().any(|item| {
+            item.windows(ORDINALS_ENVELOPE.len())
+                .any(|window| window == ORDINALS_ENVELOPE)
+        })
+    }
+}
+
+/***
+ * A single 32 byte push, a bare hash committed without any prefix. OpenTimestamps calendars and
+ * many other notarisation services commit this way and can't be told apart, so they are counted
+ * together. It is tried last so that protocols with a prefix win.
+ */
+pub struct HashCommitment;
+
+impl Tagger for HashCommitment {
+    fn name(&self) -> &'static str {
+        "hash-commitment"
+    }
+
+    fn tag_data(&self, _tx: &Transaction, _vout_idx: usize, pushes: &[Vec<u8>]) -> bool {
+        pushes.len() == 1 && pushes[0].len() == 32
+    }
+}
+
+/// The ARC4 (RC4) keystream applied to data, encryption and decryption are the same
+pub fn arc4(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut state: Vec<u8> = (0..=255).collect();
+    let mut j: u8 = 0;
+    for i in 0..256 {
+        j = j.wrapping_add(state[i]).wrapping_add(key[i % key.len()]);
+        state.swap(i, j as usize);
+    }
+
+    let (mut i, mut j) = (0u8, 0u8);
+    data.iter()
+        .map(|byte| {
+            i = i.wrapping_add(1);
+            j = j.wrapping_add(state[i as usize]);
+            state.swap(i as usize, j as usize);
+            byte ^ state[state[i as usize].wrapping_add(state[j as usize]) as usize]
+        })
+        .collect()
+}
@@ -0,0 +1,224 @@
+use crate::checkpoint::{Checkpoint, CheckpointSegment};
+use crate::layout::Layout;
+use crate::segment::{self, FileHeader, FileKind};
+use crate::{Block, Segment, Transaction, Wallet};
+use hashbrown::HashSet;
+use rayon::prelude::*;
+use std::path::{Path, PathBuf};
+
+/***
+ * Read access to the segments in a data directory. Only segments recorded in the checkpoint are
+ * visited, in height order, so files of an interrupted run are never picked up.
+ */
+pub struct DataDir {
+    dir: PathBuf,
+    layout: Layout,
+    // Recorded segments sorted by height
+    segments: Vec<CheckpointSegment>,
+}
+
+impl DataDir {
+    pub fn open(dir: &Path) -> Result<Self, String> {
+        if !dir.is_dir() {
+            return Err(format!("{} is not a directory", dir.display()));
+        }
+        let checkpoint = Checkpoint::load(dir)?;
+        let mut segments = checkpoint.get_segments().to_vec();
+        segments.sort_by_key(|segment| (segment.first_height, segment.id));
+        Ok(DataDir {
+            dir: dir.to_path_buf(),
+            layout: checkpoint.get_layout(),
+            segments,
+        })
+    }
+
+    pub fn get_segments(&self) -> &[CheckpointSegment] {
+        &self.segments
+    }
+
+    pub fn get_layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn get_dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Last height of the run of heights ingested from genesis without gaps
+    pub fn contiguous_height(&self) -> Option<u64> {
+        let mut last = None;
+        for segment in self.segments.iter() {
+            if segment.first_height > segment.last_height {
+                continue;
+            }
+            if segment.first_height != last.map_or(0, |last| last + 1) {
+                break;
+            }
+            last = Some(segment.last_height);
+        }
+        last
+    }
+
+    /// Where a recorded segment's files are depends on the layout and its first height
+    fn get_segment(&self, id: usize) -> Result<&CheckpointSegment, String> {
+        self.segments
+            .iter()
+            .find(|segment| segment.id == id)
+            .ok_or_else(|| format!("Segment {} is not in the checkpoint", id))
+    }
+
+    pub fn blocks_path(&self, id: usize) -> Result<PathBuf, String> {
+        let recorded = self.get_segment(id)?;
+        Ok(self
+            .layout
+            .blocks_path(&self.dir, id, recorded.first_height))
+    }
+
+    pub fn wallets_path(&self, id: usize) -> Result<PathBuf, String> {
+        let recorded = self.get_segment(id)?;
+        Ok(self
+            .layout
+            .wallets_path(&self.dir, id, recorded.first_height))
+    }
+
+    pub fn read_header(&self, id: usize) -> Result<FileHeader, String> {
+        segment::read_header(&self.blocks_path(id)?)
+    }
+
+    pub fn read_segment(&self, id: usize) -> Result<Segment, String> {
+        segment::read_file(&self.blocks_path(id)?, FileKind::Blocks).map(|(_, segment)| segment)
+    }
+
+    /// Replace a segment's blocks file, keeping the header it was written with
+    pub fn rewrite_segment(&self, segment: &Segment) -> Result<(), String> {
+        let path = self.blocks_path(segment.id)?;
+        let header = segment::read_header(&path)?;
+        segment::write_file(&path, header, segment)
+    }
+
+    pub fn read_wallets(&self, id: usize) -> Result<Vec<Wallet>, String> {
+        segment::read_file(&self.wallets_path(id)?, FileKind::Wallets).map(|(_, wallets)| wallets)
+    }
+
+    /// Lazily read segments in height order, one file at a time
+    pub fn segments(&self) -> impl Iterator<Item = Result<Segment, String>> + '_ {
+        self.segments
+            .iter()
+            .map(move |recorded| self.read_segment(recorded.id))
+    }
+
+    /// Read segments on the rayon pool, in no particular order
+    pub fn par_segments(&self) -> impl ParallelIterator<Item = Result<Segment, String>> + '_ {
+        self.segments
+            .par_iter()
+            .map(move |recorded| self.read_segment(recorded.id))
+    }
+
+    /// Lazily read the blocks from genesis up to and including a height, in height order
+    pub fn blocks_up_to(&self, height: u64) -> impl Iterator<Item = Result<Block, String>> + '_ {
+        self.segments
+            .iter()
+            // Segments holding only wallets cover no heights
+            .filter(move |recorded| {
+                recorded.first_height <= recorded.last_height && recorded.first_height <= height
+            })
+            .flat_map(move |recorded| match self.read_segment(recorded.id) {
+                Ok(segment) => segment
+                    .blocks
+                    .into_iter()
+                    .filter(|block| block.height <= height)
+                    .map(Ok)
+                    .collect::<Vec<_>>(),
+                Err(e) => vec![Err(e)],
+            })
+    }
+
+    /// Network name the segments were written for, from the header of the first one
+    pub fn network(&self) -> Result<String, String> {
+        match self.segments.first() {
+            Some(recorded) => Ok(self.read_header(recorded.id)?.network),
+            None => Err(format!("No segments in {}", self.dir.display())),
+        }
+    }
+
+    /// Lazily read wallets of all segments, an address can show up in more than one segment
+    pub fn wallets(&self) -> impl Iterator<Item = Result<Wallet, String>> + '_ {
+        self.segments
+            .iter()
+            .flat_map(move |recorded| match self.read_wallets(recorded.id) {
+                Ok(wallets) => wallets.into_iter().map(Ok).collect::<Vec<_>>(),
+                Err(e) => vec![Err(e)],
+            })
+    }
+
+    /// The segment holding a height, found from the recorded ranges without reading any file
+    pub fn find_segment(&self, height: u64) -> Option<&CheckpointSegment> {
+        self.segments
+            .iter()
+            .find(|segment| segment.first_height <= height && height <= segment.last_height)
+    }
+
+    pub fn find_block_by_height(&self, height: u64) -> Result<Option<Block>, String> {
+        let recorded = match self.find_segment(height) {
+            Some(recorded) => recorded,
+            None => return Ok(None),
+        };
+        let segment = self.read_segment(recorded.id)?;
+        Ok(segment
+            .blocks
+            .into_iter()
+            .find(|block| block.height == height))
+    }
+
+    pub fn find_block_by_hash(&self, hash: &str) -> Result<Option<Block>, String> {
+        self.par_segments()
+            .map(|segment| {
+                segment.map(|segment| segment.blocks.into_iter().find(|block| block.hash == hash))
+            })
+            .find_map_any(|found| found.transpose())
+            .transpose()
+    }
+
+    /// Scan all segments for a txid, returns the height of the block it is in with it
+    pub fn find_transaction(&self, txid: &str) -> Result<Option<(u64, Transaction)>, String> {
+        let hash = xxhash_rust::const_xxh3::xxh3_64(txid.as_bytes());
+        self.par_segments()
+            .map(|segment| {
+                segment.map(|segment| {
+                    segment.blocks.into_iter().find_map(|block| {
+                        let height = block.height;
+                        block
+                            .transactions
+                            .into_iter()
+                            .find(|tx| tx.hash == hash && tx.txid == txid)
+                            .map(|tx| (height, tx))
+                    })
+                })
+            })
+            .find_map_any(|found| found.transpose())
+            .transpose()
+    }
+
+    /// Look up the addresses of wallet hashes, scanning the wallet files in parallel
+    pub fn find_wallets(&self, hashes: &[u64]) -> Result<Vec<Wallet>, String> {
+        let hashes: HashSet<u64> = hashes.iter().copied().collect();
+        let mut found: Vec<Wallet> = self
+            .segments
+            .par_iter()
+            .map(|recorded| {
+                self.read_wallets(recorded.id).map(|wallets| {
+                    wallets
+                        .into_iter()
+                        .filter(|wallet| hashes.contains(&wallet.hash))
+                        .collect::<Vec<Wallet>>()
+                })
+            })
+            .collect::<Result<Vec<Vec<Wallet>>, String>>()?
+            .into_iter()
+            .flatten()
+            .collect();
+        found.sort_by_key(|wallet| wallet.hash);
+        found.dedup_by_key(|wallet| wallet.hash);
+        Ok(found)
+    }
+}
@@ -0,0 +1,158 @@
+use crate::change;
+use crate::coinbase;
+use crate::coinjoin;
+use crate::fees;
+use crate::reader::DataDir;
+use crate::{Prevout, ScriptType, Transaction, Vin};
+use log::{info, warn};
+
+/***
+ * Fills every input with the wallet and value of the output it spends. Segments are replayed in
+ * height order against an index of the outputs that are still unspent, so an input always finds
+ * its output in an earlier block or earlier in its own block. With the inputs known the change of
+ * every transaction is labelled, see change.rs, PayJoin candidates are flagged, see
+ * coinjoin.rs, and fees are computed per transaction and block, see fees.rs, along with what the
+ * coinbase left unclaimed.
+ *
+ * Inputs only hold the hash of the txid they spend, not the txid itself, so outputs can't be asked
+ * for at the node and every height up to the inputs has to be ingested. Inputs spending outputs
+ * below the first ingested height stay unresolved.
+ *
+ * Memory is bounded by the UTXO set at the height being replayed, not by the number of segments:
+ * the index holds every output unspent at that point, about 56 bytes each with the hash map's
+ * overhead and up to twice that right after the map grows. Unlike the UTXO snapshot builder, see
+ * utxo.rs, there is no memory cap. Every input of a block needs its output in the same pass for
+ * the fee and change labels, so the index can't be split into partitions replayed one by one.
+ */
+pub struct OutputIndex {
+    // (txid hash, output index) of unspent outputs
+    outputs: hashbrown::HashMap<(u64, u32), Prevout>,
+}
+
+impl Default for OutputIndex {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OutputIndex {
+    pub fn new() -> Self {
+        OutputIndex {
+            outputs: hashbrown::HashMap::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.outputs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.outputs.is_empty()
+    }
+
+    /// Add the outputs of a transaction. OP_RETURN outputs can never be spent and are left out, a
+    /// duplicate txid (BIP30) replaces the outputs of the earlier transaction.
+    pub fn add_transaction(&mut self, tx: &Transaction) {
+        for (vout_idx, vout) in tx.vouts.iter().enumerate() {
+            if vout.script_type != ScriptType::OpReturn {
+                self.outputs
+                    .insert((tx.hash, vout_idx as u32), Prevout::new(vout));
+            }
+        }
+    }
+
+    /// Remove the output an input spends from the index
+    pub fn spend(&mut self, vin: &Vin) -> Option<Prevout> {
+        self.outputs.remove(&(vin.txid_hash, vin.vout_idx))
+    }
+}
+
+#[derive(Default)]
+pub struct ResolveStats {
+    pub resolved: u64,
+    pub unresolved: u64,
+    // Segments written back because inputs, labels or fees in them changed
+    pub rewritten: usize,
+}
+
+/// Resolve the inputs of every segment in the data directory, rewriting the segments that change
+pub fn resolve(data: &DataDir) -> Result<ResolveStats, String> {
+    let mut index = OutputIndex::new();
+    // Wallets paid to so far, for the new address heuristic
+    let mut seen: hashbrown::HashSet<u64> = hashbrown::HashSet::new();
+    let mut stats = ResolveStats::default();
+    let mut next_height = 0;
+
+    for recorded in data.get_segments() {
+        // Segments holding only wallets cover no heights
+        if recorded.first_height > recorded.last_height {
+            continue;
+        }
+        if recorded.first_height != next_height {
+            warn!(
+                "Heights {}..{} are not ingested, inputs spending them stay unresolved",
+                next_height, recorded.first_height
+            );
+        }
+        next_height = recorded.last_height + 1;
+
+        let mut segment = data.read_segment(recorded.id)?;
+        let mut changed = false;
+        for block in segment.blocks.iter_mut() {
+            for tx in block.transactions.iter_mut() {
+                for vin in tx.vins.iter_mut() {
+                    let prevout = index.spend(vin);
+                    match prevout {
+                        Some(_) => stats.resolved += 1,
+                        None => stats.unresolved += 1,
+                    }
+                    if vin.prevout != prevout {
+                        vin.prevout = prevout;
+                        changed = true;
+                    }
+                }
+                let fee = fees::transaction_fee(tx);
+                if tx.fee != fee {
+                    tx.fee = fee;
+                    changed = true;
+                }
+                let collaborative = coinjoin::detect(tx);
+                if tx.collaborative != collaborative {
+                    tx.collaborative = collaborative;
+                    changed = true;
+                }
+                let labels = change::label(tx, |wallet| !seen.contains(&wallet));
+                if tx.change != labels {
+                    tx.change = labels;
+                    changed = true;
+                }
+                seen.extend(tx.vouts.iter().filter_map(|vout| vout.wallet));
+                index.add_transaction(tx);
+            }
+            let block_fees = fees::block_fees(block);
+            if block.fees != block_fees {
+                block.fees = block_fees;
+                changed = true;
+            }
+            if let (Some(coinbase), Some(fees)) = (block.coinbase.as_mut(), block.fees.as_ref()) {
+                let under_claimed = coinbase::under_claimed(coinbase, fees);
+                if coinbase.under_claimed != under_claimed {
+                    coinbase.under_claimed = under_claimed;
+                    changed = true;
+                }
+            }
+        }
+
+        if changed {
+            data.rewrite_segment(&segment)?;
+            stats.rewritten += 1;
+        }
+        info!(
+            "Resolved heights {}..={}, {} unspent outputs",
+            recorded.first_height,
+            recorded.last_height,
+            index.len()
+        );
+    }
+    Ok(stats)
+}
@@ -0,0 +1,96 @@
+use crate::balance::History;
+
+/***
+ * How the coins held by addresses are distributed at a height: the largest balances, the number
+ * of addresses per decade of balance from 1 sat to 10k BTC, the Gini coefficient and the share of
+ * the top 1% and 10% of addresses. Only addresses with a positive balance are counted.
+ */
+pub struct RichList {
+    // Wallet hash and balance of the richest addresses, largest first
+    pub top: Vec<(u64, u64)>,
+    pub buckets: Vec<Bucket>,
+    pub addresses: u64,
+    pub total: u64,
+    pub gini: f64,
+    pub top_1_share: f64,
+    pub top_10_share: f64,
+}
+
+pub struct Bucket {
+    // Inclusive lower bound in sat, the bucket ends at the next one's
+    pub min: u64,
+    pub addresses: u64,
+    pub total: u64,
+}
+
+// 1 sat to 10k BTC in powers of ten, the last bucket holds everything above
+const BUCKET_DECADES: u32 = 13;
+
+impl RichList {
+    pub fn new(history: &History, top: usize) -> Self {
+        let mut balances: Vec<(u64, u64)> = history
+            .addresses
+            .iter()
+            .filter(|(_, stats)| stats.balance() > 0)
+            .map(|(hash, stats)| (*hash, stats.balance() as u64))
+            .collect();
+        // Richest first, the hash keeps ties in a stable order
+        balances.sort_unstable_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+
+        let mut buckets: Vec<Bucket> = (0..BUCKET_DECADES)
+            .map(|decade| Bucket {
+                min: 10u64.pow(decade),
+                addresses: 0,
+                total: 0,
+            })
+            .collect();
+        for (_, balance) in balances.iter() {
+            let idx = (balance.ilog10() as usize).min(buckets.len() - 1);
+            buckets[idx].addresses += 1;
+            buckets[idx].total += balance;
+        }
+
+        let total: u64 = balances.iter().map(|(_, balance)| balance).sum();
+        let share = |percent: usize| {
+            if total == 0 {
+                return 0.0;
+            }
+            let count = (balances.len() * percent).div_ceil(100);
+            let held: u64 = balances
+                .iter()
+                .take(count)
+                .map(|(_, balance)| balance)
+                .sum();
+            held as f64 / total as f64
+        };
+        let (top_1_share, top_10_share) = (share(1), share(10));
+
+        RichList {
+            gini: gini(&balances),
+            top: balances.iter().take(top).cloned().collect(),
+            buckets,
+            addresses: balances.len() as u64,
+            total,
+            top_1_share,
+            top_10_share,
+        }
+    }
+}
+
+/// Gini coefficient of balances sorted from largest to smallest, 0 when every address holds the
+/// same, a lone address included, and (n - 1) / n when one of n addresses holds nearly everything
+fn gini(balances: &[(u64, u64)]) -> f64 {
+    let n = balances.len() as f64;
+    let total: f64 = balances.iter().map(|(_, balance)| *balance as f64).sum();
+    if total == 0.0 {
+        return 0.0;
+    }
+    // With x ascending and i from 1: G = 2 * sum(i * x_i) / (n * sum(x)) - (n + 1) / n
+    let weighted: f64 = balances
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, (_, balance))| (i + 1) as f64 * *balance as f64)
+        .sum();
+    2.0 * weighted / (n * total) - (n + 1.0) / n
+}
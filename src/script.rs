@@ -0,0 +1,189 @@
+use crate::model::ScriptType;
+use bech32::{u5, ToBase32, Variant};
+use bitcoincore_rpc as bitcoin;
+use bitcoincore_rpc::bitcoin::blockdata::{opcodes::all::*, script::Instruction};
+
+/// Classify an output script the way Bitcoin Core's Solver does
+pub fn script_type(script: &bitcoincore_rpc::bitcoin::Script) -> ScriptType {
+    if script.is_p2pk() {
+        ScriptType::P2PK
+    } else if script.is_p2pkh() {
+        ScriptType::P2PKH
+    } else if script.is_p2sh() {
+        ScriptType::P2SH
+    } else if script.is_v0_p2wpkh() {
+        ScriptType::P2WPKH
+    } else if script.is_v0_p2wsh() {
+        ScriptType::P2WSH
+    } else if script.is_witness_program() {
+        // OP_1 followed by a 32 byte push is a taproot output key
+        let bytes = script.as_bytes();
+        if bytes[0] == OP_PUSHNUM_1.into_u8() && bytes.len() == 34 {
+            ScriptType::P2TR
+        } else if bytes[0] == 0 {
+            // Version 0 with any other length can never be spent
+            ScriptType::NonStandard
+        } else {
+            ScriptType::WitnessUnknown
+        }
+    } else if script.is_op_return() {
+        ScriptType::OpReturn
+    } else if multisig_keys(script).is_some() {
+        ScriptType::Multisig
+    } else {
+        ScriptType::NonStandard
+    }
+}
+
+/// The data pushed by an OP_RETURN script, other opcodes are skipped and a push that runs past
+/// the end of the script ends it
+pub fn data_pushes(script: &bitcoincore_rpc::bitcoin::Script) -> Vec<Vec<u8>> {
+    let mut pushes = Vec::new();
+    for instruction in script.instructions().skip(1) {
+        match instruction {
+            Ok(Instruction::PushBytes(bytes)) => pushes.push(bytes.to_vec()),
+            Ok(Instruction::Op(_)) => {}
+            Err(_) => break,
+        }
+    }
+    pushes
+}
+
+/***
+ * Parse a bare multisig script, OP_m <pubkey>... OP_n OP_CHECKMULTISIG, into the number of
+ * required signatures and the keys. Keys are only checked for their length, not for being valid
+ * curve points.
+ */
+pub fn multisig_keys(script: &bitcoincore_rpc::bitcoin::Script) -> Option<(u8, Vec<&[u8]>)> {
+    let instructions = script
+        .instructions()
+        .collect::<Result<Vec<Instruction>, _>>()
+        .ok()?;
+    let (first, rest) = instructions.split_first()?;
+    let (last, rest) = rest.split_last()?;
+    let (nr_keys, keys) = rest.split_last()?;
+    match last {
+        Instruction::Op(op) if *op == OP_CHECKMULTISIG => {}
+        _ => return None,
+    }
+    let required = small_int(first)?;
+    let nr_keys = small_int(nr_keys)?;
+    if required == 0 || required > nr_keys || nr_keys as usize != keys.len() {
+        return None;
+    }
+    keys.iter()
+        .map(|key| match key {
+            Instruction::PushBytes(bytes) if bytes.len() == 33 || bytes.len() == 65 => Some(*bytes),
+            _ => None,
+        })
+        .collect::<Option<Vec<&[u8]>>>()
+        .map(|keys| (required, keys))
+}
+
+// OP_1 to OP_16
+pub(crate) fn small_int(instruction: &Instruction) -> Option<u8> {
+    match instruction {
+        Instruction::Op(op)
+            if op.into_u8() >= OP_PUSHNUM_1.into_u8()
+                && op.into_u8() <= OP_PUSHNUM_16.into_u8() =>
+        {
+            Some(op.into_u8() - OP_PUSHNUM_1.into_u8() + 1)
+        }
+        _ => None,
+    }
+}
+
+/***
+ * Render the address an output script pays to. P2PKH and P2SH are base58, witness programs are
+ * bech32 for version 0 and bech32m (BIP350) for version 1 and up, and a P2PK output is rendered as
+ * the P2PKH address of its key.
+ */
+pub fn script_to_address(
+    script: &bitcoincore_rpc::bitcoin::Script,
+    network: bitcoin::bitcoin::Network,
+) -> Result<String, String> {
+    if script.is_witness_program() {
+        return script_to_witness_address(script, network);
+    }
+    match bitcoin::bitcoin::util::address::Address::from_script(script, network) {
+        Some(address) => Ok(address.to_string()),
+        None => {
+            if script.is_p2pk() {
+                return script_to_p2pk(script, network);
+            }
+            Err("Not a p2pk script".to_string())
+        }
+    }
+}
+
+/// Encode a witness program, bitcoin 0.26 only knows bech32 and gets taproot addresses wrong
+pub fn script_to_witness_address(
+    script: &bitcoincore_rpc::bitcoin::Script,
+    network: bitcoin::bitcoin::Network,
+) -> Result<String, String> {
+    if !script.is_witness_program() {
+        return Err("Not a witness program".to_string());
+    }
+    let bytes = script.as_bytes();
+    // OP_0, or OP_PUSHNUM_1 to OP_PUSHNUM_16 for versions 1 to 16
+    let version = match bytes[0] {
+        0 => 0,
+        op => op - OP_PUSHNUM_1.into_u8() + 1,
+    };
+    let program = &bytes[2..];
+    // Version 0 only defines 20 byte key hashes and 32 byte script hashes
+    if version == 0 && program.len() != 20 && program.len() != 32 {
+        return Err(format!(
+            "Invalid witness v0 program length {}",
+            program.len()
+        ));
+    }
+
+    let hrp = match network {
+        bitcoin::bitcoin::Network::Bitcoin => "bc",
+        bitcoin::bitcoin::Network::Testnet | bitcoin::bitcoin::Network::Signet => "tb",
+        bitcoin::bitcoin::Network::Regtest => "bcrt",
+    };
+    let variant = if version == 0 {
+        Variant::Bech32
+    } else {
+        Variant::Bech32m
+    };
+    let mut data = vec![u5::try_from_u8(version).map_err(|e| e.to_string())?];
+    data.extend(program.to_base32());
+    bech32::encode(hrp, data, variant).map_err(|e| format!("Failed to encode address: {}", e))
+}
+
+pub fn script_to_p2pk(
+    script: &bitcoincore_rpc::bitcoin::Script,
+    network: bitcoin::bitcoin::Network,
+) -> Result<String, String> {
+    match p2pk_key(script) {
+        Some(pubkey) => pubkey_to_p2pkh(pubkey, network),
+        None => Err("Not a p2pk script".to_string()),
+    }
+}
+
+/// The serialized public key of a P2PK script, <pubkey> OP_CHECKSIG
+pub fn p2pk_key(script: &bitcoincore_rpc::bitcoin::Script) -> Option<&[u8]> {
+    if !script.is_p2pk() {
+        return None;
+    }
+    let bytes = script.as_bytes();
+    Some(&bytes[1..bytes.len() - 1])
+}
+
+/// The P2PKH address of a serialized public key, used as the identity of keys in P2PK and bare
+/// multisig outputs
+pub fn pubkey_to_p2pkh(
+    pubkey: &[u8],
+    network: bitcoin::bitcoin::Network,
+) -> Result<String, String> {
+    match bitcoin::bitcoin::PublicKey::from_slice(pubkey) {
+        Ok(pubkey) => {
+            let addr = bitcoin::bitcoin::util::address::Address::p2pkh(&pubkey, network);
+            Ok(addr.to_string())
+        }
+        Err(e) => Err(format!("Failed to parse pubkey: {}", e)),
+    }
+}
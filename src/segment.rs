@@ -0,0 +1,220 @@
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+/***
+ * On-disk layout of blocks-N.dat and wallets-N.dat:
+ *
+ *   magic         8 bytes, "BUTTSEG\0"
+ *   version       u32 LE, FORMAT_VERSION of the writer
+ *   header length u32 LE
+ *   header        bincode FileHeader
+ *   body          bincode Segment, Vec<Wallet>, Vec<Utxo> or Clustering, header.body_length
+ *                 bytes
+ *   checksum      u64 LE, xxh3 of header and body
+ *
+ * The magic and version come first in a fixed layout so that a reader can reject files from
+ * another format version before trying to decode anything.
+ */
+pub const MAGIC: &[u8; 8] = b"BUTTSEG\0";
+// Bump whenever the header or any of the serialized data model structs change
+pub const FORMAT_VERSION: u32 = 10;
+
+#[derive(Serialize, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
+pub enum FileKind {
+    Blocks,
+    Wallets,
+    // Partition of a UTXO snapshot, segment_id is the partition index
+    Utxos,
+    Clusters,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct FileHeader {
+    pub kind: FileKind,
+    // Network name as in bitcoin::Network's Display, e.g. "bitcoin" or "testnet"
+    pub network: String,
+    pub segment_id: usize,
+    // Inclusive height range of the segment, first > last when it holds no blocks
+    pub first_height: u64,
+    pub last_height: u64,
+    // Blocks or wallets, depending on the kind
+    pub nr_records: u64,
+    pub nr_transactions: u64,
+    pub body_length: u64,
+}
+
+/// Write header, body and checksum to a temporary file first so an abort never leaves a half
+/// written file behind
+pub fn write_file<T: Serialize>(
+    path: &Path,
+    mut header: FileHeader,
+    body: &T,
+) -> Result<(), String> {
+    let body = bincode::serialize(body).map_err(|e| format!("Failed to serialize body: {}", e))?;
+    header.body_length = body.len() as u64;
+    let header =
+        bincode::serialize(&header).map_err(|e| format!("Failed to serialize header: {}", e))?;
+    let checksum = checksum(&header, &body);
+
+    let tmp_path = path.with_extension("dat.tmp");
+    let file = File::create(&tmp_path)
+        .map_err(|e| format!("Failed to create {}: {}", tmp_path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    writer
+        .write_all(MAGIC)
+        .and_then(|_| writer.write_all(&FORMAT_VERSION.to_le_bytes()))
+        .and_then(|_| writer.write_all(&(header.len() as u32).to_le_bytes()))
+        .and_then(|_| writer.write_all(&header))
+        .and_then(|_| writer.write_all(&body))
+        .and_then(|_| writer.write_all(&checksum.to_le_bytes()))
+        .and_then(|_| writer.flush())
+        .and_then(|_| writer.get_ref().sync_all())
+        .map_err(|e| format!("Failed to write {}: {}", tmp_path.display(), e))?;
+
+    std::fs::rename(&tmp_path, path)
+        .map_err(|e| format!("Failed to rename {}: {}", tmp_path.display(), e))
+}
+
+/// Write a value as pretty JSON to a temporary file and rename it over path, so readers never see a
+/// half written file
+pub fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), String> {
+    let tmp_path = path.with_extension("json.tmp");
+    let file = File::create(&tmp_path)
+        .map_err(|e| format!("Failed to create {}: {}", tmp_path.display(), e))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut writer, value)
+        .map_err(|e| format!("Failed to serialize {}: {}", path.display(), e))?;
+    writer
+        .flush()
+        .and_then(|_| writer.get_ref().sync_all())
+        .map_err(|e| format!("Failed to write {}: {}", tmp_path.display(), e))?;
+    std::fs::rename(&tmp_path, path)
+        .map_err(|e| format!("Failed to replace {}: {}", path.display(), e))
+}
+
+/// Read only the header, without reading or verifying the body
+pub fn read_header(path: &Path) -> Result<FileHeader, String> {
+    let (mut reader, file_length) = open(path)?;
+    let (header, _, _) = read_header_from(path, &mut reader, file_length)?;
+    Ok(header)
+}
+
+/// Read and verify a whole file. Fails on a different kind, format version or a bad checksum
+pub fn read_file<T: DeserializeOwned>(
+    path: &Path,
+    kind: FileKind,
+) -> Result<(FileHeader, T), String> {
+    let (mut reader, file_length) = open(path)?;
+    let (header, header_bytes, remaining) = read_header_from(path, &mut reader, file_length)?;
+    if header.kind != kind {
+        return Err(format!(
+            "{} holds {:?}, expected {:?}",
+            path.display(),
+            header.kind,
+            kind
+        ));
+    }
+
+    // Check the length before allocating, a corrupt header could ask for any amount
+    if header.body_length.checked_add(8) != Some(remaining) {
+        return Err(format!(
+            "{} is truncated/corrupt, the header gives a body of {} bytes but {} bytes are left",
+            path.display(),
+            header.body_length,
+            remaining
+        ));
+    }
+    let mut body = vec![0u8; header.body_length as usize];
+    let mut checksum_bytes = [0u8; 8];
+    reader
+        .read_exact(&mut body)
+        .and_then(|_| reader.read_exact(&mut checksum_bytes))
+        .map_err(|e| format!("{} is truncated: {}", path.display(), e))?;
+
+    let expected = u64::from_le_bytes(checksum_bytes);
+    let actual = checksum(&header_bytes, &body);
+    if expected != actual {
+        return Err(format!(
+            "{} is corrupt, checksum {:016x} does not match {:016x}",
+            path.display(),
+            actual,
+            expected
+        ));
+    }
+
+    let body = bincode::deserialize(&body)
+        .map_err(|e| format!("Failed to decode {}: {}", path.display(), e))?;
+    Ok((header, body))
+}
+
+/// Open a file along with its length
+fn open(path: &Path) -> Result<(BufReader<File>, u64), String> {
+    let file = File::open(path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+    let file_length = file
+        .metadata()
+        .map_err(|e| format!("Failed to read metadata of {}: {}", path.display(), e))?
+        .len();
+    Ok((BufReader::new(file), file_length))
+}
+
+/// Read up to the end of the header, also returning the header bytes for the checksum and the
+/// number of bytes left in the file after them
+fn read_header_from(
+    path: &Path,
+    reader: &mut BufReader<File>,
+    file_length: u64,
+) -> Result<(FileHeader, Vec<u8>, u64), String> {
+    let mut magic = [0u8; 8];
+    let mut version = [0u8; 4];
+    let mut header_length = [0u8; 4];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|e| format!("{} is too short for a segment file: {}", path.display(), e))?;
+    if &magic != MAGIC {
+        return Err(format!("{} is not a segment file", path.display()));
+    }
+
+    reader
+        .read_exact(&mut version)
+        .and_then(|_| reader.read_exact(&mut header_length))
+        .map_err(|e| format!("{} is truncated: {}", path.display(), e))?;
+    let version = u32::from_le_bytes(version);
+    if version != FORMAT_VERSION {
+        return Err(format!(
+            "{} has format version {}, this build reads version {}",
+            path.display(),
+            version,
+            FORMAT_VERSION
+        ));
+    }
+
+    let header_length = u32::from_le_bytes(header_length) as u64;
+    // Magic, version and header length come first
+    let remaining = file_length.saturating_sub(16);
+    if header_length > remaining {
+        return Err(format!(
+            "{} is truncated/corrupt, the header is {} bytes but {} bytes are left",
+            path.display(),
+            header_length,
+            remaining
+        ));
+    }
+    let mut header_bytes = vec![0u8; header_length as usize];
+    reader
+        .read_exact(&mut header_bytes)
+        .map_err(|e| format!("{} is truncated: {}", path.display(), e))?;
+    let header = bincode::deserialize(&header_bytes)
+        .map_err(|e| format!("Failed to decode header of {}: {}", path.display(), e))?;
+    Ok((header, header_bytes, remaining - header_length))
+}
+
+fn checksum(header: &[u8], body: &[u8]) -> u64 {
+    let mut hasher = xxhash_rust::xxh3::Xxh3::new();
+    hasher.update(header);
+    hasher.update(body);
+    hasher.digest()
+}
@@ -0,0 +1,152 @@
+use crate::blkfile::BlkIndex;
+use crate::network;
+use bitcoincore_rpc::bitcoin::{consensus::encode, Block, BlockHash, Network};
+use bitcoincore_rpc::RpcApi;
+use hashbrown::HashMap;
+use std::path::Path;
+
+/***
+ * Where blocks come from. Ingestion only needs the chain height and blocks by height, so anything
+ * that can hand out consensus blocks can drive it: a node over RPC, the raw blk files, or a set of
+ * recorded blocks.
+ */
+pub trait BlockSource: Sync {
+    /// Height of the chain tip
+    fn get_tip_height(&self) -> Result<u64, String>;
+
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash, String>;
+
+    fn get_block(&self, hash: &BlockHash) -> Result<Block, String>;
+
+    fn get_block_by_height(&self, height: u64) -> Result<Block, String> {
+        let hash = self.get_block_hash(height)?;
+        self.get_block(&hash)
+    }
+
+    /// Network of the chain, told apart by its genesis block. None for a custom chain
+    fn get_network(&self) -> Result<Option<Network>, String> {
+        let genesis = self.get_block_hash(0)?;
+        Ok(network::from_genesis_hash(&genesis))
+    }
+}
+
+impl BlockSource for bitcoincore_rpc::Client {
+    fn get_tip_height(&self) -> Result<u64, String> {
+        self.get_block_count()
+            .map_err(|e| format!("Failed to get block count: {}", e))
+    }
+
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash, String> {
+        RpcApi::get_block_hash(self, height)
+            .map_err(|e| format!("Failed to get block hash at height {}: {}", height, e))
+    }
+
+    fn get_block(&self, hash: &BlockHash) -> Result<Block, String> {
+        RpcApi::get_block(self, hash).map_err(|e| format!("Failed to get block {}: {}", hash, e))
+    }
+
+    fn get_network(&self) -> Result<Option<Network>, String> {
+        let info = self
+            .get_blockchain_info()
+            .map_err(|e| format!("Failed to get blockchain info: {}", e))?;
+        network::from_chain_name(&info.chain).map(Some)
+    }
+}
+
+impl BlockSource for BlkIndex {
+    fn get_tip_height(&self) -> Result<u64, String> {
+        Ok(self.get_tip_height())
+    }
+
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash, String> {
+        self.get_block_hash(height)
+            .ok_or_else(|| format!("No block at height {}", height))
+    }
+
+    fn get_block(&self, hash: &BlockHash) -> Result<Block, String> {
+        let height = self
+            .get_height(hash)
+            .ok_or_else(|| format!("Block {} is not in the best chain", hash))?;
+        self.get_block(height)
+    }
+
+    fn get_block_by_height(&self, height: u64) -> Result<Block, String> {
+        self.get_block(height)
+    }
+}
+
+/***
+ * Blocks held in memory, ordered by height starting at 0. Used to replay recorded blocks without
+ * a node, e.g. fixtures captured with `bitcoin-cli getblock <hash> 0`.
+ */
+pub struct MemorySource {
+    blocks: Vec<Block>,
+    heights: HashMap<BlockHash, usize>,
+}
+
+impl MemorySource {
+    pub fn new(blocks: Vec<Block>) -> Result<Self, String> {
+        if blocks.is_empty() {
+            return Err("Expected at least one block".to_string());
+        }
+        for (height, pair) in blocks.windows(2).enumerate() {
+            if pair[1].header.prev_blockhash != pair[0].block_hash() {
+                return Err(format!(
+                    "Block {} at height {} does not extend {}",
+                    pair[1].block_hash(),
+                    height + 1,
+                    pair[0].block_hash()
+                ));
+            }
+        }
+        let heights = blocks
+            .iter()
+            .enumerate()
+            .map(|(height, block)| (block.block_hash(), height))
+            .collect();
+        Ok(MemorySource { blocks, heights })
+    }
+
+    /// Parse one hex encoded block per line, as printed by `getblock <hash> 0`
+    pub fn from_hex(hex_blocks: &str) -> Result<Self, String> {
+        let blocks = hex_blocks
+            .lines()
+            .map(|line| line.trim())
+            .filter(|line| !line.is_empty())
+            .enumerate()
+            .map(|(line_nr, line)| {
+                let bytes = hex::decode(line)
+                    .map_err(|e| format!("Invalid hex on line {}: {}", line_nr + 1, e))?;
+                encode::deserialize::<Block>(&bytes)
+                    .map_err(|e| format!("Invalid block on line {}: {}", line_nr + 1, e))
+            })
+            .collect::<Result<Vec<Block>, String>>()?;
+        MemorySource::new(blocks)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let hex_blocks = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        MemorySource::from_hex(&hex_blocks)
+    }
+}
+
+impl BlockSource for MemorySource {
+    fn get_tip_height(&self) -> Result<u64, String> {
+        Ok(self.blocks.len() as u64 - 1)
+    }
+
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash, String> {
+        self.blocks
+            .get(height as usize)
+            .map(|block| block.block_hash())
+            .ok_or_else(|| format!("No block at height {}", height))
+    }
+
+    fn get_block(&self, hash: &BlockHash) -> Result<Block, String> {
+        self.heights
+            .get(hash)
+            .map(|height| self.blocks[*height].clone())
+            .ok_or_else(|| format!("Unknown block {}", hash))
+    }
+}
@@ -0,0 +1,137 @@
+use crate::network;
+use crate::reader::DataDir;
+use crate::Block;
+use bitcoincore_rpc as bitcoin;
+use std::str::FromStr;
+
+/***
+ * Audit of the coins issued by the coinbases against the subsidy schedule. A block issues what its
+ * coinbase claims minus the fees it collects, which is at most the subsidy of its height. Summed
+ * up from genesis that is the issued supply, below the theoretical curve by whatever coinbases
+ * left unclaimed. The genesis coinbase counts as issued even though it can never be spent.
+ *
+ * Issuance of a block is only exact once the resolve pass knows its fees, until then the claim is
+ * counted up to the subsidy.
+ */
+/// Blocks of one subsidy era, between two halvings
+#[derive(Clone, Debug)]
+pub struct Era {
+    pub first_height: u64,
+    pub subsidy: u64,
+    pub blocks: u64,
+    // Subsidy of the blocks by the schedule and what their coinbases issued
+    pub expected: u64,
+    pub issued: u64,
+}
+
+pub struct Audit {
+    network: bitcoin::bitcoin::Network,
+    // Height of the last block added
+    pub height: Option<u64>,
+    pub blocks: u64,
+    pub eras: Vec<Era>,
+    pub expected: u64,
+    pub issued: u64,
+    pub under_claimed: u64,
+    // (height, amount) of coinbases claiming less or more than the subsidy plus fees
+    pub under_claimed_blocks: Vec<(u64, u64)>,
+    pub over_claimed_blocks: Vec<(u64, u64)>,
+    // Heights from BIP34 on whose coinbase doesn't start with the height
+    pub bip34_mismatches: Vec<u64>,
+    // Blocks whose fees aren't known yet
+    pub unresolved: u64,
+}
+
+impl Audit {
+    pub fn new(network: bitcoin::bitcoin::Network) -> Self {
+        Audit {
+            network,
+            height: None,
+            blocks: 0,
+            eras: Vec::new(),
+            expected: 0,
+            issued: 0,
+            under_claimed: 0,
+            under_claimed_blocks: Vec::new(),
+            over_claimed_blocks: Vec::new(),
+            bip34_mismatches: Vec::new(),
+            unresolved: 0,
+        }
+    }
+
+    /// Add the next block, a new era starts when its height passes a halving
+    pub fn add_block(&mut self, block: &Block) -> Result<(), String> {
+        let height = block.height;
+        let coinbase = match &block.coinbase {
+            Some(coinbase) => coinbase,
+            None => return Err(format!("Block {} has no coinbase", height)),
+        };
+        let subsidy = network::subsidy(self.network, height);
+        if height >= network::bip34_height(self.network) && coinbase.height != Some(height) {
+            self.bip34_mismatches.push(height);
+        }
+
+        let issued = match &block.fees {
+            Some(fees) => {
+                if coinbase.claimed > subsidy + fees.total {
+                    self.over_claimed_blocks
+                        .push((height, coinbase.claimed - subsidy - fees.total));
+                }
+                let under_claimed = (subsidy + fees.total).saturating_sub(coinbase.claimed);
+                if under_claimed > 0 {
+                    self.under_claimed_blocks.push((height, under_claimed));
+                    self.under_claimed += under_claimed;
+                }
+                coinbase.claimed.saturating_sub(fees.total)
+            }
+            None => {
+                self.unresolved += 1;
+                coinbase.claimed.min(subsidy)
+            }
+        };
+
+        let first_height = height - height % network::halving_interval(self.network);
+        if self
+            .eras
+            .last()
+            .is_none_or(|era| era.first_height != first_height)
+        {
+            self.eras.push(Era {
+                first_height,
+                subsidy,
+                blocks: 0,
+                expected: 0,
+                issued: 0,
+            });
+        }
+        if let Some(era) = self.eras.last_mut() {
+            era.blocks += 1;
+            era.expected += subsidy;
+            era.issued += issued;
+        }
+        self.height = Some(height);
+        self.blocks += 1;
+        self.expected += subsidy;
+        self.issued += issued;
+        Ok(())
+    }
+
+    /// The theoretical supply at the last height, the subsidy of every block from genesis
+    pub fn theoretical(&self) -> u64 {
+        self.height.map_or(0, |height| {
+            network::cumulative_subsidy(self.network, height)
+        })
+    }
+}
+
+/// Audit the coinbases from genesis up to and including a height
+pub fn audit(data: &DataDir, height: u64) -> Result<Audit, String> {
+    let network = data.network()?;
+    let network = bitcoin::bitcoin::Network::from_str(&network)
+        .map_err(|e| format!("Unknown network {}: {}", network, e))?;
+    let mut audit = Audit::new(network);
+    for block in data.blocks_up_to(height) {
+        audit.add_block(&block?)?;
+    }
+    Ok(audit)
+}
@@ -0,0 +1,400 @@
+use crate::reader::DataDir;
+use crate::segment::{self, FileHeader, FileKind};
+use crate::{Prevout, ScriptType, Transaction};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+};
+
+/***
+ * The set of unspent outputs at a height, replayed from the segments in height order. Snapshots
+ * are kept in the data directory, one subdirectory per height:
+ *
+ *   data/utxo/0100000/utxos-0-of-1.dat
+ *   data/utxo/0100000/snapshot.json
+ *
+ * With a memory cap the outpoints are split into partitions by txid hash and every partition is
+ * replayed on its own, a partition that outgrows the cap is split in two and replayed again. A
+ * snapshot is only complete once snapshot.json is written, and a later snapshot starts from the
+ * highest complete one below it instead of from genesis.
+ *
+ * Like Bitcoin Core the genesis coinbase and OP_RETURN outputs are never added, and a coinbase
+ * repeating the txid of an earlier one (BIP30, heights 91842 and 91880 on mainnet) replaces its
+ * outputs. Core also leaves out outputs with scripts over 10000 bytes, which aren't recognised
+ * here, so the totals can differ from gettxoutsetinfo by those.
+ */
+const UTXO_DIR: &str = "utxo";
+const SNAPSHOT_FILE: &str = "snapshot.json";
+// Rough size of an entry in the in-memory set, including the hash map's overhead
+const ENTRY_BYTES: u64 = 80;
+const MAX_PARTITIONS: u64 = 1 << 16;
+
+/// An unspent output as written to the snapshot files
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Utxo {
+    pub txid_hash: u64,
+    pub vout_idx: u32,
+    // Height of the block that created the output
+    pub height: u64,
+    pub coinbase: bool,
+    pub output: Prevout,
+}
+
+/// The outpoints whose txid hash is index modulo count
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Partition {
+    pub index: u64,
+    pub count: u64,
+}
+
+impl Partition {
+    pub const ALL: Partition = Partition { index: 0, count: 1 };
+
+    pub fn contains(&self, txid_hash: u64) -> bool {
+        txid_hash % self.count == self.index
+    }
+
+    /// The two halves of the partition
+    pub fn split(&self) -> (Partition, Partition) {
+        let count = self.count * 2;
+        (
+            Partition {
+                index: self.index,
+                count,
+            },
+            Partition {
+                index: self.index + self.count,
+                count,
+            },
+        )
+    }
+
+    fn file_name(&self) -> String {
+        format!("utxos-{}-of-{}.dat", self.index, self.count)
+    }
+}
+
+/// Totals of a snapshot, the same numbers gettxoutsetinfo reports
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub struct Snapshot {
+    pub height: u64,
+    // Transactions with at least one unspent output
+    pub transactions: u64,
+    pub txouts: u64,
+    pub total_amount: u64,
+    // Outputs replaced by a duplicate coinbase txid since genesis
+    pub bip30_overwritten: u64,
+    pub partitions: Vec<Partition>,
+}
+
+impl Snapshot {
+    pub fn dir(data_dir: &Path, height: u64) -> PathBuf {
+        data_dir.join(UTXO_DIR).join(format!("{:07}", height))
+    }
+
+    pub fn load(data_dir: &Path, height: u64) -> Result<Option<Self>, String> {
+        let path = Self::dir(data_dir, height).join(SNAPSHOT_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let file =
+            File::open(&path).map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        serde_json::from_reader(file)
+            .map(Some)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))
+    }
+
+    /// Heights with a complete snapshot, in ascending order
+    pub fn list(data_dir: &Path) -> Result<Vec<u64>, String> {
+        let dir = data_dir.join(UTXO_DIR);
+        if !dir.is_dir() {
+            return Ok(Vec::new());
+        }
+        let entries = std::fs::read_dir(&dir)
+            .map_err(|e| format!("Failed to list {}: {}", dir.display(), e))?;
+        let mut heights: Vec<u64> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.path().join(SNAPSHOT_FILE).exists())
+            .filter_map(|entry| entry.file_name().to_str()?.parse().ok())
+            .collect();
+        heights.sort_unstable();
+        Ok(heights)
+    }
+
+    /// Read the outputs of one partition file of the snapshot
+    pub fn read_partition(
+        &self,
+        data_dir: &Path,
+        partition: Partition,
+    ) -> Result<Vec<Utxo>, String> {
+        let path = Self::dir(data_dir, self.height).join(partition.file_name());
+        segment::read_file(&path, FileKind::Utxos).map(|(_, utxos)| utxos)
+    }
+
+    /// Lazily read the outputs of the snapshot, one partition file at a time
+    pub fn utxos<'a>(
+        &'a self,
+        data_dir: &'a Path,
+    ) -> impl Iterator<Item = Result<Vec<Utxo>, String>> + 'a {
+        self.partitions
+            .iter()
+            .map(move |partition| self.read_partition(data_dir, *partition))
+    }
+
+    fn save(&self, data_dir: &Path) -> Result<(), String> {
+        segment::write_json(&Self::dir(data_dir, self.height).join(SNAPSHOT_FILE), self)
+    }
+}
+
+struct Coin {
+    height: u64,
+    coinbase: bool,
+    output: Prevout,
+}
+
+/// The unspent outputs of one partition while replaying
+pub struct UtxoSet {
+    partition: Partition,
+    coins: hashbrown::HashMap<(u64, u32), Coin>,
+    bip30_overwritten: u64,
+    // Inputs of the partition spending an output that isn't in the set
+    missing_spends: u64,
+}
+
+impl UtxoSet {
+    pub fn new(partition: Partition) -> Self {
+        UtxoSet {
+            partition,
+            coins: hashbrown::HashMap::new(),
+            bip30_overwritten: 0,
+            missing_spends: 0,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.coins.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.coins.is_empty()
+    }
+
+    pub fn get_bip30_overwritten(&self) -> u64 {
+        self.bip30_overwritten
+    }
+
+    /// Whether an output is unspent, outputs of other partitions never are
+    pub fn contains(&self, txid_hash: u64, vout_idx: u32) -> bool {
+        self.coins.contains_key(&(txid_hash, vout_idx))
+    }
+
+    pub fn add_utxo(&mut self, utxo: Utxo) {
+        if self.partition.contains(utxo.txid_hash) {
+            let coin = Coin {
+                height: utxo.height,
+                coinbase: utxo.coinbase,
+                output: utxo.output,
+            };
+            self.coins.insert((utxo.txid_hash, utxo.vout_idx), coin);
+        }
+    }
+
+    /// Spend the inputs of a transaction and add its outputs
+    pub fn add_transaction(&mut self, height: u64, tx: &Transaction) {
+        for vin in tx.vins.iter() {
+            if self.partition.contains(vin.txid_hash)
+                && self.coins.remove(&(vin.txid_hash, vin.vout_idx)).is_none()
+            {
+                self.missing_spends += 1;
+            }
+        }
+        // The genesis coinbase can't be spent and never enters the set
+        if height == 0 || !self.partition.contains(tx.hash) {
+            return;
+        }
+        let coinbase = tx.vins.is_empty();
+        for (vout_idx, vout) in tx.vouts.iter().enumerate() {
+            if vout.script_type == ScriptType::OpReturn {
+                continue;
+            }
+            let coin = Coin {
+                height,
+                coinbase,
+                output: Prevout::new(vout),
+            };
+            if self
+                .coins
+                .insert((tx.hash, vout_idx as u32), coin)
+                .is_some()
+            {
+                self.bip30_overwritten += 1;
+            }
+        }
+    }
+
+    fn into_utxos(self) -> Vec<Utxo> {
+        let mut utxos: Vec<Utxo> = self
+            .coins
+            .into_iter()
+            .map(|((txid_hash, vout_idx), coin)| Utxo {
+                txid_hash,
+                vout_idx,
+                height: coin.height,
+                coinbase: coin.coinbase,
+                output: coin.output,
+            })
+            .collect();
+        utxos.sort_unstable_by_key(|utxo| (utxo.txid_hash, utxo.vout_idx));
+        utxos
+    }
+}
+
+/***
+ * Build the snapshot at a height, or return it if it exists. Every height up to it has to be
+ * ingested. memory_cap is in bytes, without one the whole set is kept in memory in one pass.
+ */
+pub fn build(data: &DataDir, height: u64, memory_cap: Option<u64>) -> Result<Snapshot, String> {
+    let data_dir = data.get_dir();
+    if let Some(snapshot) = Snapshot::load(data_dir, height)? {
+        return Ok(snapshot);
+    }
+    let ingested = data.contiguous_height();
+    if ingested.is_none_or(|ingested| ingested < height) {
+        return Err(format!(
+            "Heights 0..={} have to be ingested, the data directory holds 0..={}",
+            height,
+            ingested.map_or("none".to_string(), |ingested| ingested.to_string())
+        ));
+    }
+
+    // Start from the closest snapshot below
+    let base = match Snapshot::list(data_dir)?
+        .into_iter()
+        .rfind(|base| *base < height)
+    {
+        Some(base) => Snapshot::load(data_dir, base)?,
+        None => None,
+    };
+    match &base {
+        Some(base) => info!(
+            "Building the UTXO set at {} from the snapshot at {}",
+            height, base.height
+        ),
+        None => info!("Building the UTXO set at {} from genesis", height),
+    }
+
+    let dir = Snapshot::dir(data_dir, height);
+    std::fs::create_dir_all(&dir)
+        .map_err(|e| format!("Failed to create {}: {}", dir.display(), e))?;
+    let network = data.network()?;
+    let max_entries = memory_cap.map(|cap| cap / ENTRY_BYTES);
+
+    let mut snapshot = Snapshot {
+        height,
+        bip30_overwritten: base.as_ref().map_or(0, |base| base.bip30_overwritten),
+        ..Default::default()
+    };
+    let mut pending = vec![Partition::ALL];
+    while let Some(partition) = pending.pop() {
+        let set = match replay(data, base.as_ref(), partition, height, max_entries)? {
+            Some(set) => set,
+            None => {
+                // A partition of a few outputs still not fitting means the cap is far too small
+                if partition.count >= MAX_PARTITIONS {
+                    return Err(format!(
+                        "The UTXO set doesn't fit the memory cap in {} partitions",
+                        MAX_PARTITIONS
+                    ));
+                }
+                let (low, high) = partition.split();
+                info!(
+                    "Partition {} of {} is over the memory cap, splitting it",
+                    partition.index, partition.count
+                );
+                pending.push(high);
+                pending.push(low);
+                continue;
+            }
+        };
+        if set.missing_spends > 0 {
+            warn!(
+                "{} inputs spend outputs that aren't in the UTXO set",
+                set.missing_spends
+            );
+        }
+        snapshot.bip30_overwritten += set.bip30_overwritten;
+        let utxos = set.into_utxos();
+        snapshot.txouts += utxos.len() as u64;
+        snapshot.total_amount += utxos.iter().map(|utxo| utxo.output.value).sum::<u64>();
+        snapshot.transactions += utxos
+            .windows(2)
+            .filter(|pair| pair[0].txid_hash != pair[1].txid_hash)
+            .count() as u64
+            + !utxos.is_empty() as u64;
+
+        let header = FileHeader {
+            kind: FileKind::Utxos,
+            network: network.clone(),
+            segment_id: partition.index as usize,
+            first_height: 0,
+            last_height: height,
+            nr_records: utxos.len() as u64,
+            nr_transactions: 0,
+            body_length: 0,
+        };
+        segment::write_file(&dir.join(partition.file_name()), header, &utxos)?;
+        snapshot.partitions.push(partition);
+    }
+    snapshot.save(data_dir)?;
+    Ok(snapshot)
+}
+
+/// Replay one partition up to the height, None once it holds more than max_entries outputs
+fn replay(
+    data: &DataDir,
+    base: Option<&Snapshot>,
+    partition: Partition,
+    height: u64,
+    max_entries: Option<u64>,
+) -> Result<Option<UtxoSet>, String> {
+    let over_cap = |set: &UtxoSet| max_entries.is_some_and(|max| set.len() as u64 > max);
+    let mut set = UtxoSet::new(partition);
+    let start_height = match base {
+        Some(base) => {
+            for utxos in base.utxos(data.get_dir()) {
+                for utxo in utxos? {
+                    set.add_utxo(utxo);
+                }
+                if over_cap(&set) {
+                    return Ok(None);
+                }
+            }
+            base.height + 1
+        }
+        None => 0,
+    };
+
+    for recorded in data.get_segments() {
+        if recorded.first_height > recorded.last_height
+            || recorded.last_height < start_height
+            || recorded.first_height > height
+        {
+            continue;
+        }
+        let segment = data.read_segment(recorded.id)?;
+        for block in segment.blocks.iter() {
+            if block.height < start_height || block.height > height {
+                continue;
+            }
+            for tx in block.transactions.iter() {
+                set.add_transaction(block.height, tx);
+            }
+        }
+        if over_cap(&set) {
+            return Ok(None);
+        }
+    }
+    Ok(Some(set))
+}
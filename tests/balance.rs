@@ -0,0 +1,97 @@
+use buttcoin::balance::History;
+use buttcoin::ScriptType::{self, Multisig, OpReturn, P2PKH};
+use buttcoin::{Block, Prevout, Transaction, Vin, Vout};
+
+/// Spends (value, wallet) outputs, None for an unresolved input, and pays (value, script type,
+/// wallet) outputs
+fn transaction(
+    inputs: &[Option<(u64, u64)>],
+    outputs: &[(u64, ScriptType, Option<u64>)],
+) -> Transaction {
+    let mut tx = Transaction::new(0, String::new());
+    for (vout_idx, input) in inputs.iter().enumerate() {
+        let mut vin = Vin::new(1, vout_idx as u32);
+        vin.prevout = input.map(|(value, wallet)| Prevout {
+            value,
+            script_type: P2PKH,
+            wallet: Some(wallet),
+        });
+        tx.add_vin(vin);
+    }
+    for (value, script_type, wallet) in outputs {
+        tx.add_vout(Vout::new(*value, *script_type, *wallet, None));
+    }
+    tx
+}
+
+fn block(height: u64, transactions: Vec<Transaction>) -> Block {
+    let mut block = Block::new(height, String::new(), 0);
+    for tx in transactions {
+        block.add_transaction(tx);
+    }
+    block
+}
+
+#[test]
+fn credits_and_debits() {
+    let mut history = History::new();
+    history.add_block(&block(
+        1,
+        vec![transaction(&[], &[(5_000, P2PKH, Some(1))])],
+    ));
+    history.add_block(&block(
+        2,
+        vec![transaction(
+            &[Some((5_000, 1))],
+            &[(3_000, P2PKH, Some(2)), (1_900, P2PKH, Some(1))],
+        )],
+    ));
+    history.add_block(&block(
+        3,
+        vec![
+            transaction(&[Some((3_000, 2))], &[(2_900, P2PKH, Some(3))]),
+            // Wallet 3 is paid twice by one transaction
+            transaction(&[None], &[(100, P2PKH, Some(3)), (200, P2PKH, Some(3))]),
+        ],
+    ));
+
+    let stats = &history.addresses[&1];
+    assert_eq!((stats.received, stats.sent), (6_900, 5_000));
+    assert_eq!(stats.balance(), 1_900);
+    assert_eq!((stats.first_seen, stats.last_seen), (1, 2));
+    // Spending and getting change in one transaction is one transaction
+    assert_eq!(stats.tx_count, 2);
+
+    let stats = &history.addresses[&2];
+    assert_eq!(stats.balance(), 0);
+    assert_eq!((stats.first_seen, stats.last_seen), (2, 3));
+    assert_eq!(stats.tx_count, 2);
+
+    let stats = &history.addresses[&3];
+    assert_eq!(stats.balance(), 3_200);
+    assert_eq!((stats.first_seen, stats.last_seen), (3, 3));
+    assert_eq!(stats.tx_count, 2);
+
+    assert_eq!(history.unresolved, 1);
+    assert_eq!(history.addresses.len(), 3);
+}
+
+#[test]
+fn outputs_without_an_address() {
+    let mut history = History::new();
+    history.add_block(&block(
+        1,
+        vec![transaction(
+            &[Some((10_000, 1))],
+            &[
+                (0, OpReturn, None),
+                (5_000, Multisig, None),
+                (4_000, P2PKH, None),
+            ],
+        )],
+    ));
+    // Only the input's wallet shows up, the outputs aren't credited to anyone
+    assert_eq!(history.addresses.len(), 1);
+    assert_eq!(history.addresses[&1].balance(), -10_000);
+    assert_eq!(history.unresolved, 0);
+}
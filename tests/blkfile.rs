@@ -0,0 +1,209 @@
+use bitcoincore_rpc::bitcoin::{
+    blockdata::constants::genesis_block, blockdata::script::Builder, consensus::encode, Block,
+    BlockHash, BlockHeader, Network, OutPoint, Script, Transaction, TxIn, TxOut,
+};
+use buttcoin::blkfile::{detect_network, BlkIndex};
+use std::path::{Path, PathBuf};
+
+// Regtest's minimum difficulty, and mainnet's which is far more work per block
+const REGTEST_BITS: u32 = 0x207fffff;
+const MAINNET_BITS: u32 = 0x1d00ffff;
+
+/// A block on top of prev, the tag sets its coinbase apart from siblings
+fn child(prev: &Block, tag: u64, bits: u32) -> Block {
+    let coinbase = Transaction {
+        version: 1,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            script_sig: Builder::new().push_int(tag as i64).into_script(),
+            sequence: 0xffffffff,
+            witness: Vec::new(),
+        }],
+        output: vec![TxOut {
+            value: 5_000_000_000,
+            script_pubkey: Script::new(),
+        }],
+    };
+    let mut block = Block {
+        header: BlockHeader {
+            version: 1,
+            prev_blockhash: prev.block_hash(),
+            merkle_root: Default::default(),
+            time: prev.header.time + 600,
+            bits,
+            nonce: 0,
+        },
+        txdata: vec![coinbase],
+    };
+    block.header.merkle_root = block.merkle_root();
+    block
+}
+
+/// Genesis and nr_blocks more on top of it
+fn chain(nr_blocks: u64) -> Vec<Block> {
+    let mut blocks = vec![genesis_block(Network::Regtest)];
+    for height in 1..=nr_blocks {
+        blocks.push(child(blocks.last().unwrap(), height, REGTEST_BITS));
+    }
+    blocks
+}
+
+/// Records of <magic><length><block>, in the order given
+fn records(blocks: &[&Block]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for block in blocks {
+        let block = encode::serialize(*block);
+        bytes.extend_from_slice(&Network::Regtest.magic().to_le_bytes());
+        bytes.extend_from_slice(&(block.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&block);
+    }
+    bytes
+}
+
+/// A fresh blocks directory for a test
+fn blocks_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("buttcoin-blk-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Write a blk file, obfuscated the way Bitcoin Core does when xor.dat holds a key
+fn write_blk_file(dir: &Path, file_nr: u32, bytes: &[u8], xor_key: Option<[u8; 8]>) {
+    let bytes: Vec<u8> = match xor_key {
+        Some(key) => bytes
+            .iter()
+            .enumerate()
+            .map(|(pos, byte)| byte ^ key[pos % 8])
+            .collect(),
+        None => bytes.to_vec(),
+    };
+    std::fs::write(dir.join(format!("blk{:05}.dat", file_nr)), bytes).unwrap();
+}
+
+fn hashes(index: &BlkIndex) -> Vec<BlockHash> {
+    (0..=index.get_tip_height())
+        .map(|height| index.get_block_hash(height).unwrap())
+        .collect()
+}
+
+/// Blocks stored out of height order across two files, with an orphan and zeroed preallocation
+fn check_index(name: &str, xor_key: Option<[u8; 8]>) {
+    let dir = blocks_dir(name);
+    if let Some(key) = xor_key {
+        std::fs::write(dir.join("xor.dat"), key).unwrap();
+    }
+    let blocks = chain(4);
+    // Its parent never made it into the files
+    let orphan = child(&child(&blocks[4], 50, REGTEST_BITS), 51, REGTEST_BITS);
+    let mut first = records(&[&blocks[0], &blocks[2], &blocks[1]]);
+    first.extend_from_slice(&[0u8; 64]);
+    write_blk_file(&dir, 0, &first, xor_key);
+    write_blk_file(
+        &dir,
+        1,
+        &records(&[&orphan, &blocks[4], &blocks[3]]),
+        xor_key,
+    );
+
+    assert_eq!(detect_network(&dir), Ok(Network::Regtest));
+    let index = BlkIndex::open(&dir, Network::Regtest).unwrap();
+    assert_eq!(index.get_tip_height(), 4);
+    let expected: Vec<BlockHash> = blocks.iter().map(|block| block.block_hash()).collect();
+    assert_eq!(hashes(&index), expected);
+    assert_eq!(index.get_height(&orphan.block_hash()), None);
+    for (height, block) in blocks.iter().enumerate() {
+        assert_eq!(index.get_block(height as u64).as_ref(), Ok(block));
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn orders_blocks_by_height() {
+    check_index("plain", None);
+}
+
+#[test]
+fn undoes_xor_obfuscation() {
+    check_index(
+        "xor",
+        Some([0x5a, 0x01, 0xff, 0x80, 0x13, 0x37, 0x00, 0xc3]),
+    );
+}
+
+#[test]
+fn all_zero_xor_key_is_no_key() {
+    check_index("zero-xor", Some([0u8; 8]));
+}
+
+#[test]
+fn stops_at_truncated_record() {
+    let dir = blocks_dir("truncated");
+    let blocks = chain(3);
+    let mut bytes = records(&[&blocks[0], &blocks[1], &blocks[2], &blocks[3]]);
+    // Cut the last record short, as an unclean shutdown can
+    bytes.truncate(bytes.len() - 10);
+    write_blk_file(&dir, 0, &bytes, None);
+
+    let index = BlkIndex::open(&dir, Network::Regtest).unwrap();
+    assert_eq!(index.get_tip_height(), 2);
+    assert_eq!(index.get_block(2).as_ref(), Ok(&blocks[2]));
+    assert!(index.get_block(3).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn rejects_another_network() {
+    let dir = blocks_dir("magic");
+    write_blk_file(&dir, 0, &records(&[&chain(0)[0]]), None);
+    assert!(BlkIndex::open(&dir, Network::Bitcoin).is_err());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn follows_most_work() {
+    let dir = blocks_dir("work");
+    let blocks = chain(3);
+    // One block at mainnet difficulty outweighs three at regtest's
+    let heavy = child(&blocks[0], 100, MAINNET_BITS);
+    write_blk_file(
+        &dir,
+        0,
+        &records(&[&blocks[0], &blocks[1], &blocks[2], &blocks[3], &heavy]),
+        None,
+    );
+    let index = BlkIndex::open(&dir, Network::Regtest).unwrap();
+    assert_eq!(
+        hashes(&index),
+        vec![blocks[0].block_hash(), heavy.block_hash()]
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn equal_work_keeps_first_stored() {
+    let blocks = chain(2);
+    let fork_parent = child(&blocks[0], 200, REGTEST_BITS);
+    let fork = child(&fork_parent, 201, REGTEST_BITS);
+    let cases: &[(&str, Vec<&Block>, Vec<&Block>)] = &[
+        (
+            "chain first",
+            vec![&blocks[0], &blocks[1], &blocks[2], &fork_parent, &fork],
+            vec![&blocks[0], &blocks[1], &blocks[2]],
+        ),
+        (
+            "fork first",
+            vec![&blocks[0], &fork_parent, &fork, &blocks[1], &blocks[2]],
+            vec![&blocks[0], &fork_parent, &fork],
+        ),
+    ];
+    for (name, stored, expected) in cases {
+        let dir = blocks_dir(&name.replace(' ', "-"));
+        write_blk_file(&dir, 0, &records(stored), None);
+        let index = BlkIndex::open(&dir, Network::Regtest).unwrap();
+        let expected: Vec<BlockHash> = expected.iter().map(|block| block.block_hash()).collect();
+        assert_eq!(hashes(&index), expected, "{}", name);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
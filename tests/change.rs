@@ -0,0 +1,166 @@
+use buttcoin::change::{label, likely_change};
+use buttcoin::ScriptType::{self, OpReturn, P2PKH, P2SH, P2TR, P2WPKH};
+use buttcoin::{ChangeLabel, Heuristic, Prevout, Transaction, Vin, Vout};
+
+/// Output i pays wallet 100 + i, OP_RETURN outputs pay no one. An input without a script type is
+/// unresolved
+fn transaction(inputs: &[(u64, Option<ScriptType>)], outputs: &[(u64, ScriptType)]) -> Transaction {
+    let mut tx = Transaction::new(0, String::new());
+    for (vout_idx, (value, script_type)) in inputs.iter().enumerate() {
+        let mut vin = Vin::new(1, vout_idx as u32);
+        vin.prevout = script_type.map(|script_type| Prevout {
+            value: *value,
+            script_type,
+            wallet: Some(1),
+        });
+        tx.add_vin(vin);
+    }
+    for (idx, (value, script_type)) in outputs.iter().enumerate() {
+        let wallet = match script_type {
+            OpReturn => None,
+            _ => Some(100 + idx as u64),
+        };
+        tx.add_vout(Vout::new(*value, *script_type, wallet, None));
+    }
+    tx
+}
+
+// Name, inputs, outputs, wallets paid for the first time and the expected labels
+type LabelCase<'a> = (
+    &'a str,
+    &'a [(u64, Option<ScriptType>)],
+    &'a [(u64, ScriptType)],
+    &'a [u64],
+    &'a [(u32, Heuristic)],
+);
+
+const CASES: &[LabelCase] = &[
+    (
+        "only the change address is new",
+        &[(10_000_000, Some(P2WPKH))],
+        &[(3_000_001, P2PKH), (6_989_999, P2SH)],
+        &[101],
+        &[(1, Heuristic::NewAddress)],
+    ),
+    (
+        "both addresses are new",
+        &[(10_000_000, Some(P2WPKH))],
+        &[(3_000_001, P2PKH), (6_989_999, P2SH)],
+        &[100, 101],
+        &[],
+    ),
+    (
+        "only the change isn't round",
+        &[(10_000_000, Some(P2WPKH))],
+        &[(3_000_000, P2PKH), (6_989_999, P2SH)],
+        &[],
+        &[(1, Heuristic::RoundAmount)],
+    ),
+    (
+        "both are round",
+        &[(10_000_000, Some(P2WPKH))],
+        &[(3_000_000, P2PKH), (6_900_000, P2SH)],
+        &[],
+        &[],
+    ),
+    (
+        "only the change has the input script type",
+        &[(10_000_000, Some(P2WPKH)), (10_000_000, Some(P2WPKH))],
+        &[(3_000_001, P2WPKH), (6_989_999, P2PKH)],
+        &[],
+        &[(0, Heuristic::ScriptType)],
+    ),
+    (
+        "inputs of mixed script types",
+        &[(10_000_000, Some(P2WPKH)), (10_000_000, Some(P2PKH))],
+        &[(3_000_001, P2WPKH), (6_989_999, P2PKH)],
+        &[],
+        &[],
+    ),
+    (
+        "only the change is smaller than every input",
+        &[(5_000_000, Some(P2WPKH)), (3_000_000, Some(P2PKH))],
+        &[(7_000_001, P2SH), (900_001, P2TR)],
+        &[],
+        &[(1, Heuristic::OptimalChange)],
+    ),
+    (
+        "both are smaller than every input",
+        &[(10_000_000, Some(P2WPKH)), (9_000_000, Some(P2PKH))],
+        &[(7_000_001, P2SH), (900_001, P2TR)],
+        &[],
+        &[],
+    ),
+    (
+        "heuristics agreeing",
+        &[(10_000_000, Some(P2WPKH))],
+        &[(5_000_000, P2PKH), (4_990_000, P2WPKH)],
+        &[101],
+        &[
+            (1, Heuristic::NewAddress),
+            (1, Heuristic::RoundAmount),
+            (1, Heuristic::ScriptType),
+        ],
+    ),
+    (
+        "an unresolved input",
+        &[(10_000_000, Some(P2WPKH)), (0, None)],
+        &[(5_000_000, P2PKH), (4_990_000, P2WPKH)],
+        &[101],
+        &[],
+    ),
+    (
+        "a single candidate besides OP_RETURN",
+        &[(10_000_000, Some(P2WPKH))],
+        &[(9_990_000, P2WPKH), (0, OpReturn)],
+        &[100],
+        &[],
+    ),
+    (
+        "a coinbase",
+        &[],
+        &[(5_000_000_000, P2WPKH), (1_000, P2PKH)],
+        &[100],
+        &[],
+    ),
+];
+
+#[test]
+fn heuristics() {
+    for (name, inputs, outputs, new_wallets, expected) in CASES {
+        let tx = transaction(inputs, outputs);
+        let labels: Vec<(u32, Heuristic)> = label(&tx, |wallet| new_wallets.contains(&wallet))
+            .iter()
+            .map(|label| (label.vout_idx, label.heuristic))
+            .collect();
+        assert_eq!(labels, *expected, "{}", name);
+    }
+}
+
+fn change_label(vout_idx: u32, heuristic: Heuristic, confidence: u8) -> ChangeLabel {
+    ChangeLabel {
+        vout_idx,
+        heuristic,
+        confidence,
+    }
+}
+
+#[test]
+fn likely_change_needs_agreement() {
+    let agreeing = [
+        change_label(1, Heuristic::NewAddress, 60),
+        change_label(1, Heuristic::RoundAmount, 70),
+    ];
+    assert_eq!(likely_change(&agreeing, 50), Some(1));
+    assert_eq!(likely_change(&agreeing, 70), Some(1));
+    assert_eq!(likely_change(&agreeing, 80), None);
+
+    let disagreeing = [
+        change_label(0, Heuristic::ScriptType, 50),
+        change_label(1, Heuristic::RoundAmount, 70),
+    ];
+    assert_eq!(likely_change(&disagreeing, 50), None);
+    // Below the minimum confidence a label doesn't count against the others
+    assert_eq!(likely_change(&disagreeing, 60), Some(1));
+    assert_eq!(likely_change(&[], 0), None);
+}
@@ -0,0 +1,268 @@
+use buttcoin::cluster::{
+    Clusterer, Clustering, CollaborativeTransactions, EqualOutputs, Exclusion, Exclusions,
+    MaxInputs, UnionFind,
+};
+use buttcoin::{
+    Block, ChangeLabel, Collaborative, Heuristic, Prevout, ScriptType, Transaction, Vin, Vout,
+};
+
+/// Spends an output of every input wallet and pays (value, wallet) outputs
+fn transaction(inputs: &[u64], outputs: &[(u64, u64)]) -> Transaction {
+    let mut tx = Transaction::new(0, String::new());
+    for (vout_idx, wallet) in inputs.iter().enumerate() {
+        let mut vin = Vin::new(1, vout_idx as u32);
+        vin.prevout = Some(Prevout {
+            value: 1_000_000,
+            script_type: ScriptType::P2WPKH,
+            wallet: Some(*wallet),
+        });
+        tx.add_vin(vin);
+    }
+    for (value, wallet) in outputs {
+        tx.add_vout(Vout::new(*value, ScriptType::P2WPKH, Some(*wallet), None));
+    }
+    tx
+}
+
+// A union and the sets it leaves, as groups of indexes
+type UnionCase<'a> = ((u32, u32), &'a [&'a [u32]]);
+
+#[test]
+fn union_find() {
+    let mut sets = UnionFind::default();
+    let idx: Vec<u32> = (0..8).map(|_| sets.add()).collect();
+    assert_eq!(idx, (0..8).collect::<Vec<u32>>());
+
+    let cases: &[UnionCase] = &[
+        ((0, 1), &[&[0, 1]]),
+        ((2, 3), &[&[0, 1], &[2, 3]]),
+        // Again, or the other way round, changes nothing
+        ((1, 0), &[&[0, 1], &[2, 3]]),
+        ((1, 3), &[&[0, 1, 2, 3]]),
+        ((4, 5), &[&[0, 1, 2, 3], &[4, 5]]),
+        ((6, 5), &[&[0, 1, 2, 3], &[4, 5, 6]]),
+        ((6, 2), &[&[0, 1, 2, 3, 4, 5, 6]]),
+    ];
+    for ((a, b), groups) in cases {
+        sets.union(*a, *b);
+        for idx in 0..8 {
+            let group = groups.iter().find(|group| group.contains(&idx));
+            for other in 0..8 {
+                let together = group.is_some_and(|group| group.contains(&other)) || idx == other;
+                assert_eq!(
+                    sets.find(idx) == sets.find(other),
+                    together,
+                    "{} and {} after union({}, {})",
+                    idx,
+                    other,
+                    a,
+                    b
+                );
+            }
+        }
+    }
+    // A root is its own set
+    let root = sets.find(0);
+    assert_eq!(sets.find(root), root);
+    assert_eq!(sets.find(7), 7);
+}
+
+#[test]
+fn exclusion_rules() {
+    let mut collaborative = transaction(&[1, 2], &[(500, 3), (400, 4)]);
+    collaborative.collaborative = Some(Collaborative::PayJoin);
+    let equal_three = transaction(&[1, 2, 3], &[(500, 4), (500, 5), (500, 6), (100, 7)]);
+    let equal_two_inputs = transaction(&[1, 2], &[(500, 4), (500, 5), (500, 6)]);
+    let payment = transaction(&[1, 2, 3, 4, 5, 6], &[(500, 7), (400, 8)]);
+
+    let equal_outputs = EqualOutputs { min_outputs: 3 };
+    let max_inputs = MaxInputs { max_inputs: 5 };
+    // Exclusion, transaction and whether it is excluded
+    let cases: &[(&dyn Exclusion, &Transaction, bool)] = &[
+        (&CollaborativeTransactions, &collaborative, true),
+        (&CollaborativeTransactions, &equal_three, false),
+        (&equal_outputs, &equal_three, true),
+        (&equal_outputs, &equal_two_inputs, false),
+        (&equal_outputs, &collaborative, false),
+        (&max_inputs, &payment, true),
+        (&max_inputs, &equal_three, false),
+    ];
+    for (exclusion, tx, excluded) in cases {
+        assert_eq!(exclusion.excludes(tx), *excluded, "{}", exclusion.name());
+    }
+
+    let exclusions = Exclusions::default();
+    assert_eq!(exclusions.excludes(&collaborative), Some("collaborative"));
+    assert_eq!(exclusions.excludes(&equal_three), Some("equal-outputs"));
+    assert_eq!(exclusions.excludes(&payment), None);
+    assert_eq!(Exclusions::empty().excludes(&collaborative), None);
+}
+
+/// Wallets 1 to 8 are paid, then spent together in a plain payment with change, an equal output
+/// CoinJoin, a flagged PayJoin and a single input payment
+fn blocks() -> Vec<Block> {
+    let mut funding = Block::new(0, String::new(), 0);
+    funding.add_transaction(transaction(
+        &[],
+        &(1..=8)
+            .map(|wallet| (1_000_000, wallet))
+            .collect::<Vec<_>>(),
+    ));
+
+    let mut spending = Block::new(1, String::new(), 0);
+    let mut payment = transaction(&[1, 2], &[(1_500_000, 20), (490_000, 21)]);
+    payment.change = vec![
+        ChangeLabel {
+            vout_idx: 1,
+            heuristic: Heuristic::NewAddress,
+            confidence: 60,
+        },
+        ChangeLabel {
+            vout_idx: 1,
+            heuristic: Heuristic::RoundAmount,
+            confidence: 70,
+        },
+    ];
+    spending.add_transaction(payment);
+    spending.add_transaction(transaction(
+        &[3, 4, 5],
+        &[(990_000, 30), (990_000, 31), (990_000, 32)],
+    ));
+    let mut payjoin = transaction(&[6, 7], &[(1_990_000, 40)]);
+    payjoin.collaborative = Some(Collaborative::PayJoin);
+    spending.add_transaction(payjoin);
+    spending.add_transaction(transaction(&[8], &[(990_000, 50)]));
+    vec![funding, spending]
+}
+
+/// Clusters of more than one address, as sorted wallet hashes
+fn merged(clustering: &Clustering) -> Vec<Vec<u64>> {
+    let mut clusters: Vec<Vec<u64>> = vec![Vec::new(); clustering.clusters.len()];
+    for (wallet, id) in clustering.addresses.iter() {
+        clusters[*id as usize].push(*wallet);
+    }
+    let mut merged: Vec<Vec<u64>> = clusters
+        .into_iter()
+        .filter(|cluster| cluster.len() > 1)
+        .map(|mut cluster| {
+            cluster.sort_unstable();
+            cluster
+        })
+        .collect();
+    merged.sort();
+    merged
+}
+
+/// Exclusions the way `buttcoin cluster` builds them from merge_collaborative, equal_outputs and
+/// max_inputs
+fn exclusions(
+    merge_collaborative: bool,
+    equal_outputs: usize,
+    max_inputs: Option<usize>,
+) -> Exclusions {
+    let mut exclusions = Exclusions::empty();
+    if !merge_collaborative {
+        exclusions.add(Box::new(CollaborativeTransactions));
+    }
+    if equal_outputs > 0 {
+        exclusions.add(Box::new(EqualOutputs {
+            min_outputs: equal_outputs,
+        }));
+    }
+    if let Some(max_inputs) = max_inputs {
+        exclusions.add(Box::new(MaxInputs { max_inputs }));
+    }
+    exclusions
+}
+
+// Name, merge_collaborative, equal_outputs, max_inputs, change_confidence and the clusters merged
+type MergeCase<'a> = (
+    &'a str,
+    bool,
+    usize,
+    Option<usize>,
+    Option<u8>,
+    &'a [&'a [u64]],
+);
+
+#[test]
+fn merge_rules() {
+    let cases: &[MergeCase] = &[
+        ("defaults", false, 3, None, None, &[&[1, 2]]),
+        (
+            "merge collaborative",
+            true,
+            3,
+            None,
+            None,
+            &[&[1, 2], &[6, 7]],
+        ),
+        (
+            "no equal output exclusion",
+            false,
+            0,
+            None,
+            None,
+            &[&[1, 2], &[3, 4, 5]],
+        ),
+        (
+            "nothing excluded",
+            true,
+            0,
+            None,
+            None,
+            &[&[1, 2], &[3, 4, 5], &[6, 7]],
+        ),
+        ("max inputs", true, 0, Some(2), None, &[&[1, 2], &[6, 7]]),
+        ("confident change", false, 3, None, Some(50), &[&[1, 2, 21]]),
+        // The labels agree on the change, but with less confidence than asked for
+        ("unconfident change", false, 3, None, Some(80), &[&[1, 2]]),
+    ];
+    for (name, merge_collaborative, equal_outputs, max_inputs, change_confidence, expected) in cases
+    {
+        let mut clusterer = Clusterer::new(exclusions(
+            *merge_collaborative,
+            *equal_outputs,
+            *max_inputs,
+        ));
+        if let Some(min_confidence) = change_confidence {
+            clusterer = clusterer.with_change(*min_confidence);
+        }
+        for block in blocks() {
+            clusterer.add_block(&block);
+        }
+        let clustering = clusterer.finish(1);
+        let expected: Vec<Vec<u64>> = expected.iter().map(|cluster| cluster.to_vec()).collect();
+        assert_eq!(merged(&clustering), expected, "{}", name);
+        // Every address paid or spent from has a cluster
+        assert_eq!(clustering.addresses.len(), 15, "{}", name);
+    }
+}
+
+#[test]
+fn cluster_totals() {
+    let mut clusterer = Clusterer::new(Exclusions::default());
+    for block in blocks() {
+        clusterer.add_block(&block);
+    }
+    let clustering = clusterer.finish(1);
+    assert_eq!(clustering.height, 1);
+    assert_eq!(clustering.excluded.get("collaborative"), Some(&1));
+    assert_eq!(clustering.excluded.get("equal-outputs"), Some(&1));
+    assert_eq!(clustering.unresolved, 0);
+
+    // Clusters are numbered in the order their first address showed up
+    let id = clustering.cluster_of(1).unwrap();
+    assert_eq!(id, 0);
+    assert_eq!(clustering.cluster_of(2), Some(id));
+    assert_eq!(clustering.cluster_of(99), None);
+    let stats = &clustering.clusters[id as usize];
+    assert_eq!(stats.addresses, 2);
+    assert_eq!((stats.received, stats.sent), (2_000_000, 2_000_000));
+    assert_eq!(stats.balance, 0);
+    assert_eq!((stats.first_seen, stats.last_seen), (0, 1));
+
+    let stats = &clustering.clusters[clustering.cluster_of(20).unwrap() as usize];
+    assert_eq!((stats.addresses, stats.balance), (1, 1_500_000));
+    assert_eq!((stats.first_seen, stats.last_seen), (1, 1));
+}
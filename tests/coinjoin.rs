@@ -0,0 +1,159 @@
+use buttcoin::coinjoin::detect;
+use buttcoin::ScriptType::{self, OpReturn, P2PKH, P2WPKH};
+use buttcoin::{Collaborative, Prevout, Transaction, Vin, Vout};
+
+/// Inputs of the given value and script type, None for an unresolved one, paying outputs
+fn transaction(inputs: &[Option<(u64, ScriptType)>], outputs: &[(u64, ScriptType)]) -> Transaction {
+    let mut tx = Transaction::new(0, String::new());
+    for (vout_idx, input) in inputs.iter().enumerate() {
+        let mut vin = Vin::new(1, vout_idx as u32);
+        vin.prevout = input.map(|(value, script_type)| Prevout {
+            value,
+            script_type,
+            wallet: Some(1),
+        });
+        tx.add_vin(vin);
+    }
+    for (idx, (value, script_type)) in outputs.iter().enumerate() {
+        let wallet = match script_type {
+            OpReturn => None,
+            _ => Some(100 + idx as u64),
+        };
+        tx.add_vout(Vout::new(*value, *script_type, wallet, None));
+    }
+    tx
+}
+
+/// nr_inputs P2WPKH inputs of a value
+fn inputs(nr_inputs: usize, value: u64) -> Vec<Option<(u64, ScriptType)>> {
+    vec![Some((value, P2WPKH)); nr_inputs]
+}
+
+/// nr_equal outputs of a value followed by change outputs of distinct values
+fn outputs(nr_equal: usize, value: u64, nr_change: usize) -> Vec<(u64, ScriptType)> {
+    let mut outputs = vec![(value, P2WPKH); nr_equal];
+    outputs.extend((0..nr_change).map(|idx| (123_457 + idx as u64, P2WPKH)));
+    outputs
+}
+
+#[test]
+fn shapes() {
+    // Wasabi 2 pays standard denominations, each a few times
+    let denominations: Vec<u64> = [5_000, 10_000, 20_000, 50_000, 100_000, 200_000, 500_000]
+        .iter()
+        .chain([8_192, 16_384, 65_536, 6_561, 19_683, 13_122].iter())
+        .copied()
+        .collect();
+    let wasabi2: Vec<(u64, ScriptType)> = (0..60)
+        .map(|idx| (denominations[idx % denominations.len()], P2WPKH))
+        .collect();
+    let distinct: Vec<(u64, ScriptType)> = (0..60)
+        .map(|idx| (1_234_567 + idx as u64, P2WPKH))
+        .collect();
+
+    let cases: Vec<(&str, Transaction, Option<Collaborative>)> = vec![
+        (
+            "whirlpool",
+            transaction(&inputs(5, 1_050_000), &outputs(5, 1_000_000, 0)),
+            Some(Collaborative::Whirlpool),
+        ),
+        (
+            "whirlpool shape off the pool denominations",
+            transaction(&inputs(5, 1_050_000), &outputs(5, 1_000_001, 0)),
+            Some(Collaborative::CoinJoin),
+        ),
+        (
+            "wasabi 1",
+            transaction(&inputs(12, 11_000_000), &outputs(10, 10_000_000, 3)),
+            Some(Collaborative::Wasabi),
+        ),
+        (
+            "wasabi 1 shape with fewer inputs than equal outputs",
+            transaction(&inputs(5, 30_000_000), &outputs(10, 10_000_000, 3)),
+            None,
+        ),
+        (
+            "wasabi 2",
+            transaction(&inputs(60, 1_000_000), &wasabi2),
+            Some(Collaborative::Wasabi),
+        ),
+        (
+            "many inputs and outputs of distinct values",
+            transaction(&inputs(60, 1_000_000), &distinct),
+            None,
+        ),
+        (
+            "joinmarket, every maker and the taker with change",
+            transaction(&inputs(4, 3_000_000), &outputs(3, 2_000_000, 3)),
+            Some(Collaborative::JoinMarket),
+        ),
+        (
+            "joinmarket, the taker without change",
+            transaction(&inputs(4, 3_000_000), &outputs(3, 2_000_000, 2)),
+            Some(Collaborative::JoinMarket),
+        ),
+        (
+            "equal outputs of another coordinator",
+            transaction(&inputs(5, 3_000_000), &outputs(4, 2_000_000, 1)),
+            Some(Collaborative::CoinJoin),
+        ),
+        (
+            "two equal outputs",
+            transaction(&inputs(3, 3_000_000), &outputs(2, 2_000_000, 1)),
+            None,
+        ),
+        (
+            "equal OP_RETURN outputs",
+            transaction(
+                &inputs(3, 3_000_000),
+                &[
+                    (0, OpReturn),
+                    (0, OpReturn),
+                    (0, OpReturn),
+                    (2_000_000, P2WPKH),
+                ],
+            ),
+            None,
+        ),
+        (
+            "payjoin candidate",
+            transaction(
+                &[Some((3_000_000, P2WPKH)), Some((1_000_000, P2WPKH))],
+                &[(2_500_000, P2WPKH), (1_490_000, P2WPKH)],
+            ),
+            Some(Collaborative::PayJoin),
+        ),
+        (
+            "payment needing every input",
+            transaction(
+                &[Some((1_000_000, P2WPKH)), Some((1_000_000, P2WPKH))],
+                &[(1_500_000, P2WPKH), (490_000, P2WPKH)],
+            ),
+            None,
+        ),
+        (
+            "payjoin shape with mixed script types",
+            transaction(
+                &[Some((3_000_000, P2WPKH)), Some((1_000_000, P2PKH))],
+                &[(2_500_000, P2WPKH), (1_490_000, P2WPKH)],
+            ),
+            None,
+        ),
+        (
+            "payjoin shape with an unresolved input",
+            transaction(
+                &[Some((3_000_000, P2WPKH)), None],
+                &[(2_500_000, P2WPKH), (1_490_000, P2WPKH)],
+            ),
+            None,
+        ),
+        (
+            "coinbase",
+            transaction(&[], &outputs(5, 1_000_000, 0)),
+            None,
+        ),
+    ];
+    for (name, tx, expected) in cases {
+        assert_eq!(detect(&tx), expected, "{}", name);
+    }
+}
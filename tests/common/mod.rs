@@ -0,0 +1,109 @@
+// Helpers shared by the integration tests that ingest a chain
+
+use bitcoincore_rpc::bitcoin::{
+    blockdata::constants::genesis_block, blockdata::script::Builder, consensus::encode,
+    hashes::Hash, Block, BlockHeader, Network, OutPoint, PubkeyHash, Script, Transaction, TxIn,
+    TxOut,
+};
+use buttcoin::checkpoint::Checkpoint;
+use buttcoin::ingest::{self, Context};
+use buttcoin::source::BlockSource;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// Transactions per segment, small enough for a few segments out of a short chain
+const THRESHOLD: u64 = 5;
+
+/// A regtest chain of genesis and nr_blocks more, one hex block per line. Every block pays its
+/// coinbase to an address of its own and spends the coinbase of the block before it
+pub fn regtest_chain(nr_blocks: u64) -> String {
+    let mut blocks = vec![genesis_block(Network::Regtest)];
+    for height in 1..=nr_blocks {
+        let prev = blocks.last().unwrap();
+        let coinbase = Transaction {
+            version: 1,
+            lock_time: 0,
+            input: vec![TxIn {
+                previous_output: OutPoint::null(),
+                script_sig: Builder::new()
+                    .push_int(height as i64)
+                    .push_slice(b"/test/")
+                    .into_script(),
+                sequence: 0xffffffff,
+                witness: Vec::new(),
+            }],
+            output: vec![TxOut {
+                value: 5_000_000_000,
+                script_pubkey: p2pkh(height),
+            }],
+        };
+        let mut txdata = vec![coinbase];
+        if height > 1 {
+            txdata.push(Transaction {
+                version: 1,
+                lock_time: 0,
+                input: vec![TxIn {
+                    previous_output: OutPoint::new(prev.txdata[0].txid(), 0),
+                    script_sig: Script::new(),
+                    sequence: 0xffffffff,
+                    witness: Vec::new(),
+                }],
+                output: vec![TxOut {
+                    value: 4_999_990_000,
+                    script_pubkey: p2pkh(height + 1000),
+                }],
+            });
+        }
+        let mut block = Block {
+            header: BlockHeader {
+                version: 1,
+                prev_blockhash: prev.block_hash(),
+                merkle_root: Default::default(),
+                time: prev.header.time + 600,
+                bits: prev.header.bits,
+                nonce: 0,
+            },
+            txdata,
+        };
+        block.header.merkle_root = block.merkle_root();
+        blocks.push(block);
+    }
+    blocks
+        .iter()
+        .map(|block| hex::encode(encode::serialize(block)))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn p2pkh(seed: u64) -> Script {
+    Script::new_p2pkh(&PubkeyHash::hash(&seed.to_le_bytes()))
+}
+
+/// A fresh directory for a test
+pub fn data_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("buttcoin-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Ingest the heights below end_height the way `buttcoin ingest` does, resuming from the checkpoint
+pub fn ingest(dir: &Path, source: &dyn BlockSource, end_height: u64) -> Result<(), String> {
+    let checkpoint = Checkpoint::load(dir).unwrap();
+    checkpoint.remove_unrecorded(dir).unwrap();
+    let blocknums = checkpoint.pending_heights(0..end_height);
+    let ctx = Arc::new(Context::new(
+        end_height,
+        blocknums.clone(),
+        THRESHOLD,
+        3,
+        checkpoint,
+        Network::Regtest,
+        dir,
+    ));
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(2)
+        .build()
+        .unwrap();
+    ingest::run(&pool, source, &blocknums, ctx)
+}
@@ -0,0 +1,107 @@
+use buttcoin::fees::{block_fees, transaction_fee};
+use buttcoin::{Block, BlockFees, Prevout, ScriptType, Transaction, Vin, Vout};
+
+fn vout(value: u64) -> Vout {
+    Vout::new(value, ScriptType::P2WPKH, Some(value), None)
+}
+
+/// A transaction spending inputs and paying outputs, None for an unresolved input
+fn transaction(inputs: &[Option<u64>], outputs: &[u64], weight: u64) -> Transaction {
+    let mut tx = Transaction::new(0, String::new());
+    for (vout_idx, input) in inputs.iter().enumerate() {
+        let mut vin = Vin::new(1, vout_idx as u32);
+        vin.prevout = input.map(|value| Prevout::new(&vout(value)));
+        tx.add_vin(vin);
+    }
+    for output in outputs {
+        tx.add_vout(vout(*output));
+    }
+    tx.weight = weight;
+    tx
+}
+
+fn coinbase() -> Transaction {
+    transaction(&[], &[5_000_000_000], 400)
+}
+
+/// A block of a coinbase and transactions with these fees, each of 1000 vbytes
+fn block(fees: &[u64]) -> Block {
+    let mut block = Block::new(1, String::new(), 0);
+    block.add_transaction(coinbase());
+    for fee in fees {
+        let mut tx = transaction(&[Some(100_000 + fee)], &[100_000], 4000);
+        tx.fee = transaction_fee(&tx);
+        block.add_transaction(tx);
+    }
+    block
+}
+
+// Name, input values, output values and the expected fee
+type FeeCase<'a> = (&'a str, &'a [Option<u64>], &'a [u64], Option<u64>);
+
+#[test]
+fn transaction_fees() {
+    let cases: &[FeeCase] = &[
+        (
+            "inputs minus outputs",
+            &[Some(70_000), Some(40_000)],
+            &[100_000],
+            Some(10_000),
+        ),
+        ("no fee", &[Some(100_000)], &[60_000, 40_000], Some(0)),
+        ("unresolved input", &[Some(70_000), None], &[10_000], None),
+        ("outputs over inputs", &[Some(10_000)], &[10_001], None),
+        ("coinbase", &[], &[5_000_000_000], None),
+    ];
+    for (name, inputs, outputs, expected) in cases {
+        let tx = transaction(inputs, outputs, 800);
+        assert_eq!(transaction_fee(&tx), *expected, "{}", name);
+    }
+}
+
+#[test]
+fn vsize_and_feerate() {
+    let mut tx = transaction(&[Some(100_000)], &[99_000], 561);
+    // 561 weight units round up to 141 vbytes
+    assert_eq!(tx.vsize(), 141);
+    assert_eq!(tx.feerate(), None);
+    tx.fee = transaction_fee(&tx);
+    assert_eq!(tx.feerate(), Some(1000 * 1000 / 141));
+}
+
+#[test]
+fn odd_number_of_transactions() {
+    let fees = block_fees(&block(&[3000, 1000, 5000, 2000, 4000])).unwrap();
+    assert_eq!(fees.total, 15_000);
+    assert_eq!(fees.min_feerate, 1000);
+    assert_eq!(fees.median_feerate, 3000);
+    assert_eq!(fees.max_feerate, 5000);
+    // Nearest rank: ceil(p * n / 100), at least the first
+    assert_eq!(fees.fee_percentiles, [1000, 2000, 3000, 4000, 5000]);
+}
+
+#[test]
+fn even_number_of_transactions() {
+    let fees = block_fees(&block(&[4000, 1000, 2000, 8000])).unwrap();
+    assert_eq!(fees.total, 15_000);
+    // The median of an even count averages the middle two
+    assert_eq!(fees.median_feerate, 3000);
+    assert_eq!(fees.fee_percentiles, [1000, 1000, 2000, 4000, 8000]);
+
+    let ten: Vec<u64> = (1..=10).map(|fee| fee * 100).collect();
+    let fees = block_fees(&block(&ten)).unwrap();
+    assert_eq!(fees.median_feerate, 550);
+    assert_eq!(fees.fee_percentiles, [100, 300, 500, 800, 900]);
+}
+
+#[test]
+fn coinbase_only_block() {
+    assert_eq!(block_fees(&block(&[])), Some(BlockFees::default()));
+}
+
+#[test]
+fn unknown_fee() {
+    let mut block = block(&[1000, 2000]);
+    block.add_transaction(transaction(&[None], &[1000], 400));
+    assert_eq!(block_fees(&block), None);
+}
@@ -0,0 +1,160 @@
+mod common;
+
+use bitcoincore_rpc::bitcoin::{Block, BlockHash};
+use buttcoin::checkpoint::Checkpoint;
+use buttcoin::reader::DataDir;
+use buttcoin::segment::{self, FileKind, FORMAT_VERSION};
+use buttcoin::source::{BlockSource, MemorySource};
+use buttcoin::Segment;
+use common::{data_dir, ingest, regtest_chain};
+use std::convert::TryInto;
+
+#[test]
+fn segments_are_contiguous() {
+    let dir = data_dir("contiguous");
+    let source = MemorySource::from_hex(&regtest_chain(19)).unwrap();
+    assert_eq!(source.get_tip_height(), Ok(19));
+    ingest(&dir, &source, 20).unwrap();
+
+    let data = DataDir::open(&dir).unwrap();
+    let mut next_height = 0;
+    let mut nr_segments = 0;
+    for recorded in data.get_segments() {
+        if recorded.first_height > recorded.last_height {
+            continue;
+        }
+        assert_eq!(recorded.first_height, next_height);
+        let segment = data.read_segment(recorded.id).unwrap();
+        let heights: Vec<u64> = segment.blocks.iter().map(|block| block.height).collect();
+        let expected: Vec<u64> = (recorded.first_height..=recorded.last_height).collect();
+        assert_eq!(heights, expected, "segment {}", recorded.id);
+        next_height = recorded.last_height + 1;
+        nr_segments += 1;
+    }
+    assert_eq!(next_height, 20);
+    assert!(nr_segments > 1, "expected several segments");
+    assert_eq!(data.contiguous_height(), Some(19));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn resumes_at_next_segment() {
+    let dir = data_dir("resume");
+    let source = MemorySource::from_hex(&regtest_chain(19)).unwrap();
+    ingest(&dir, &source, 10).unwrap();
+
+    let checkpoint = Checkpoint::load(&dir).unwrap();
+    assert_eq!(checkpoint.flushed_ranges(), vec![(0, 9)]);
+    assert_eq!(
+        checkpoint.pending_heights(0..20),
+        (10..20).collect::<Vec<u64>>()
+    );
+    let next_id = checkpoint.next_segment_id();
+
+    ingest(&dir, &source, 20).unwrap();
+    let checkpoint = Checkpoint::load(&dir).unwrap();
+    assert_eq!(checkpoint.flushed_ranges(), vec![(0, 19)]);
+    let resumed: Vec<_> = checkpoint
+        .get_segments()
+        .iter()
+        .filter(|recorded| recorded.id >= next_id)
+        .collect();
+    assert!(!resumed.is_empty());
+    assert!(resumed.iter().all(
+        |recorded| recorded.first_height >= 10 || recorded.first_height > recorded.last_height
+    ));
+    assert_eq!(
+        resumed
+            .iter()
+            .filter(|recorded| recorded.first_height <= recorded.last_height)
+            .map(|recorded| recorded.first_height)
+            .min(),
+        Some(10)
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+/// Hands out the blocks of a recorded chain but fails from a height on, like a node going away
+struct FailingSource {
+    inner: MemorySource,
+    failing_height: u64,
+}
+
+impl BlockSource for FailingSource {
+    fn get_tip_height(&self) -> Result<u64, String> {
+        self.inner.get_tip_height()
+    }
+
+    fn get_block_hash(&self, height: u64) -> Result<BlockHash, String> {
+        if height >= self.failing_height {
+            return Err(format!("Connection lost fetching height {}", height));
+        }
+        self.inner.get_block_hash(height)
+    }
+
+    fn get_block(&self, hash: &BlockHash) -> Result<Block, String> {
+        self.inner.get_block(hash)
+    }
+}
+
+#[test]
+fn failed_fetch_flushes_earlier_blocks() {
+    let dir = data_dir("failed-fetch");
+    let failing = FailingSource {
+        inner: MemorySource::from_hex(&regtest_chain(19)).unwrap(),
+        failing_height: 12,
+    };
+    let error = ingest(&dir, &failing, 20).err().unwrap();
+    assert!(error.contains("height 12"), "{}", error);
+
+    // Every chunk started before the failing one finished and was flushed
+    let checkpoint = Checkpoint::load(&dir).unwrap();
+    assert_eq!(checkpoint.flushed_ranges().first(), Some(&(0, 11)));
+    assert!(checkpoint.pending_heights(0..20).starts_with(&[12, 13, 14]));
+
+    ingest(&dir, &failing.inner, 20).unwrap();
+    let checkpoint = Checkpoint::load(&dir).unwrap();
+    assert_eq!(checkpoint.flushed_ranges(), vec![(0, 19)]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn read_file_rejects_corruption() {
+    let dir = data_dir("corrupt");
+    let source = MemorySource::from_hex(&regtest_chain(3)).unwrap();
+    ingest(&dir, &source, 4).unwrap();
+    let data = DataDir::open(&dir).unwrap();
+    let path = data.blocks_path(data.get_segments()[0].id).unwrap();
+    let original = std::fs::read(&path).unwrap();
+    assert!(segment::read_file::<Segment>(&path, FileKind::Blocks).is_ok());
+
+    // A flipped byte in the body
+    let mut bytes = original.clone();
+    let last = bytes.len() - 9;
+    bytes[last] ^= 0x01;
+    std::fs::write(&path, &bytes).unwrap();
+    let error = segment::read_file::<Segment>(&path, FileKind::Blocks)
+        .err()
+        .unwrap();
+    assert!(error.contains("checksum"), "{}", error);
+
+    // Another format version
+    let mut bytes = original.clone();
+    bytes[8..12].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+    std::fs::write(&path, &bytes).unwrap();
+    let error = segment::read_file::<Segment>(&path, FileKind::Blocks)
+        .err()
+        .unwrap();
+    assert!(error.contains("format version"), "{}", error);
+
+    // A body length past the end of the file
+    let mut bytes = original;
+    let header_length = u32::from_le_bytes(bytes[12..16].try_into().unwrap()) as usize;
+    bytes[8 + header_length..16 + header_length].copy_from_slice(&(1u64 << 62).to_le_bytes());
+    std::fs::write(&path, &bytes).unwrap();
+    let error = segment::read_file::<Segment>(&path, FileKind::Blocks)
+        .err()
+        .unwrap();
+    assert!(error.contains("truncated/corrupt"), "{}", error);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,130 @@
+use bitcoincore_rpc::bitcoin::{
+    blockdata::script::Builder, hashes::hex::FromHex, OutPoint, Script, Transaction, TxIn, TxOut,
+    Txid,
+};
+use buttcoin::protocol::{arc4, Taggers};
+use buttcoin::script::data_pushes;
+
+// Input spent by the test transactions, its txid is the Counterparty key
+const TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+
+fn transaction(txid: &str, witness: Vec<Vec<u8>>, outputs: Vec<Script>) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: 0,
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::from_hex(txid).unwrap(), 0),
+            script_sig: Script::new(),
+            sequence: 0xffffffff,
+            witness,
+        }],
+        output: outputs
+            .into_iter()
+            .map(|script_pubkey| TxOut {
+                value: 0,
+                script_pubkey,
+            })
+            .collect(),
+    }
+}
+
+fn op_return(data: &[u8]) -> Script {
+    Builder::new()
+        .push_opcode(bitcoincore_rpc::bitcoin::blockdata::opcodes::all::OP_RETURN)
+        .push_slice(data)
+        .into_script()
+}
+
+fn tag_data(tx: &Transaction) -> Option<String> {
+    let pushes = data_pushes(&tx.output[0].script_pubkey);
+    Taggers::default().tag_data(tx, 0, &pushes)
+}
+
+#[test]
+fn arc4_vectors() {
+    // The test vectors of the RC4 Wikipedia article
+    let vectors: &[(&[u8], &[u8], &str)] = &[
+        (b"Key", b"Plaintext", "bbf316e8d940af0ad3"),
+        (b"Wiki", b"pedia", "1021bf0420"),
+        (b"Secret", b"Attack at dawn", "45a01f645fc35b383552544b9bf5"),
+    ];
+    for (key, plaintext, ciphertext) in vectors {
+        assert_eq!(hex::encode(arc4(key, plaintext)), *ciphertext);
+        assert_eq!(arc4(key, &hex::decode(ciphertext).unwrap()), *plaintext);
+    }
+}
+
+#[test]
+fn counterparty() {
+    // The key is the txid of the first input in displayed byte order
+    let mut key = hex::decode(TXID).unwrap();
+    let payload = arc4(&key, b"CNTRPRTY\x00\x00\x00\x00\x00\x00\x00\x00\x01");
+    let tx = transaction(TXID, Vec::new(), vec![op_return(&payload)]);
+    assert_eq!(tag_data(&tx).as_deref(), Some("counterparty"));
+
+    // Encrypted with another key
+    key.reverse();
+    let payload = arc4(&key, b"CNTRPRTY\x00\x00\x00\x00\x00\x00\x00\x00\x01");
+    let tx = transaction(TXID, Vec::new(), vec![op_return(&payload)]);
+    assert_eq!(tag_data(&tx), None);
+}
+
+#[test]
+fn omni_and_hash_commitments() {
+    let tx = transaction(TXID, Vec::new(), vec![op_return(b"omni\x00\x00\x00\x00")]);
+    assert_eq!(tag_data(&tx).as_deref(), Some("omni"));
+
+    let tx = transaction(TXID, Vec::new(), vec![op_return(&[0xab; 32])]);
+    assert_eq!(tag_data(&tx).as_deref(), Some("hash-commitment"));
+
+    // A 32 byte Omni payload is Omni, prefixed protocols win
+    let mut payload = b"omni".to_vec();
+    payload.resize(32, 0);
+    let tx = transaction(TXID, Vec::new(), vec![op_return(&payload)]);
+    assert_eq!(tag_data(&tx).as_deref(), Some("omni"));
+
+    let tx = transaction(TXID, Vec::new(), vec![op_return(&[0xab; 20])]);
+    assert_eq!(tag_data(&tx), None);
+}
+
+#[test]
+fn ordinals() {
+    // A text/plain "hello" inscription: <key> OP_CHECKSIG OP_FALSE OP_IF "ord" 1 "text/plain" 0
+    // "hello" OP_ENDIF, followed by the control block
+    let tapscript = hex::decode(concat!(
+        "20",
+        "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        "ac0063036f7264",
+        "0101",
+        "0a746578742f706c61696e",
+        "00",
+        "0568656c6c6f",
+        "68"
+    ))
+    .unwrap();
+    let control_block = hex::decode(concat!(
+        "c1",
+        "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+    ))
+    .unwrap();
+    let signature = vec![0x01; 64];
+    let tx = transaction(
+        TXID,
+        vec![signature.clone(), tapscript, control_block.clone()],
+        Vec::new(),
+    );
+    assert_eq!(
+        Taggers::default().tag_witness(&tx, 0).as_deref(),
+        Some("ordinals")
+    );
+
+    // The same spend with a tapscript that has no envelope
+    let tapscript = hex::decode(concat!(
+        "20",
+        "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        "ac"
+    ))
+    .unwrap();
+    let tx = transaction(TXID, vec![signature, tapscript, control_block], Vec::new());
+    assert_eq!(Taggers::default().tag_witness(&tx, 0), None);
+}
@@ -0,0 +1,61 @@
+mod common;
+
+use buttcoin::reader::DataDir;
+use buttcoin::resolve::resolve;
+use buttcoin::source::MemorySource;
+use buttcoin::{Block, ScriptType};
+use common::{data_dir, ingest, regtest_chain};
+
+fn block(data: &DataDir, height: u64) -> Block {
+    data.find_block_by_height(height).unwrap().unwrap()
+}
+
+#[test]
+fn fills_prevouts_and_fees() {
+    let dir = data_dir("resolve");
+    let source = MemorySource::from_hex(&regtest_chain(9)).unwrap();
+    ingest(&dir, &source, 10).unwrap();
+    let data = DataDir::open(&dir).unwrap();
+    assert!(
+        data.get_segments().len() > 1,
+        "expected spends across segments"
+    );
+
+    // Blocks 2 to 9 each spend the coinbase of the block before
+    let stats = resolve(&data).unwrap();
+    assert_eq!(stats.resolved, 8);
+    assert_eq!(stats.unresolved, 0);
+
+    let paying = block(&data, 4);
+    let spending = block(&data, 5);
+    let prevout = spending.transactions[1].vins[0].prevout.as_ref().unwrap();
+    assert_eq!(prevout.value, 5_000_000_000);
+    assert_eq!(prevout.script_type, ScriptType::P2PKH);
+    assert!(prevout.wallet.is_some());
+    assert_eq!(prevout.wallet, paying.transactions[0].vouts[0].wallet);
+
+    assert_eq!(spending.transactions[0].fee, None);
+    assert_eq!(spending.transactions[1].fee, Some(10_000));
+    let fees = spending.fees.as_ref().unwrap();
+    assert_eq!(fees.total, 10_000);
+    assert_eq!(fees.min_feerate, fees.max_feerate);
+    // The coinbases only claim the subsidy and leave the fees
+    assert_eq!(
+        spending.coinbase.as_ref().unwrap().under_claimed,
+        Some(10_000)
+    );
+
+    // A block of only a coinbase has no fees to leave
+    let coinbase_only = block(&data, 1);
+    assert_eq!(coinbase_only.fees.as_ref().unwrap().total, 0);
+    assert_eq!(
+        coinbase_only.coinbase.as_ref().unwrap().under_claimed,
+        Some(0)
+    );
+
+    // Nothing changes the second time
+    let stats = resolve(&DataDir::open(&dir).unwrap()).unwrap();
+    assert_eq!(stats.resolved, 8);
+    assert_eq!(stats.rewritten, 0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,132 @@
+use buttcoin::balance::{AddressStats, History};
+use buttcoin::richlist::RichList;
+
+/// A history where wallet i received and sent the i-th pair
+fn history(balances: &[(u64, u64)]) -> History {
+    let mut history = History::new();
+    for (wallet, (received, sent)) in balances.iter().enumerate() {
+        history.addresses.insert(
+            wallet as u64,
+            AddressStats {
+                received: *received,
+                sent: *sent,
+                ..Default::default()
+            },
+        );
+    }
+    history
+}
+
+fn holding(balances: &[u64]) -> History {
+    history(
+        &balances
+            .iter()
+            .map(|balance| (*balance, 0))
+            .collect::<Vec<_>>(),
+    )
+}
+
+fn assert_close(actual: f64, expected: f64, name: &str) {
+    assert!(
+        (actual - expected).abs() < 1e-9,
+        "{}: {} isn't {}",
+        name,
+        actual,
+        expected
+    );
+}
+
+// Name, balances, expected Gini, top 1% share and top 10% share
+type ShareCase<'a> = (&'a str, Vec<u64>, f64, f64, f64);
+
+#[test]
+fn gini_and_shares() {
+    let mut whale = vec![1; 999];
+    whale.push(1_000_000_000_000);
+    let whale_gini = 2.0 * (499_500.0 + 1000.0 * 1e12) / (1000.0 * (999.0 + 1e12)) - 1.001;
+    let cases: &[ShareCase] = &[
+        ("no addresses", vec![], 0.0, 0.0, 0.0),
+        ("all equal", vec![1_000; 100], 0.0, 0.01, 0.1),
+        // Nothing to be unequal to, the top 1% rounds up to the one address
+        ("a lone address", vec![5_000], 0.0, 1.0, 1.0),
+        // Approaches 1 as the number of addresses grows, (n - 1) / n in the limit
+        (
+            "one address holds nearly everything",
+            whale,
+            whale_gini,
+            (1e12 + 9.0) / (1e12 + 999.0),
+            (1e12 + 99.0) / (1e12 + 999.0),
+        ),
+        ("two addresses", vec![1, 3], 0.25, 0.75, 0.75),
+    ];
+    for (name, balances, gini, top_1_share, top_10_share) in cases {
+        let list = RichList::new(&holding(balances), 10);
+        assert_close(list.gini, *gini, name);
+        assert_close(list.top_1_share, *top_1_share, name);
+        assert_close(list.top_10_share, *top_10_share, name);
+        assert_eq!(list.addresses, balances.len() as u64, "{}", name);
+        assert_eq!(list.total, balances.iter().sum::<u64>(), "{}", name);
+    }
+    assert!(RichList::new(&holding(&[1; 10_000]), 0).gini.abs() < 1e-9);
+}
+
+#[test]
+fn empty_and_spent_addresses() {
+    let list = RichList::new(&History::new(), 10);
+    assert!(list.top.is_empty());
+    assert_eq!(list.buckets.len(), 13);
+    assert!(list.buckets.iter().all(|bucket| bucket.addresses == 0));
+
+    // Fully spent addresses, and ones that sent more than they got from unresolved inputs, aren't
+    // holders
+    let list = RichList::new(&history(&[(5_000, 5_000), (1_000, 3_000), (700, 0)]), 10);
+    assert_eq!(list.top, vec![(2, 700)]);
+    assert_eq!(list.addresses, 1);
+    assert_eq!(list.total, 700);
+}
+
+#[test]
+fn bucket_boundaries() {
+    const BTC: u64 = 100_000_000;
+    // Balance and the index of the bucket it falls in, bucket i starts at 10^i sat
+    let cases: &[(u64, usize)] = &[
+        (1, 0),
+        (9, 0),
+        (10, 1),
+        (99, 1),
+        (100, 2),
+        (BTC - 1, 7),
+        (BTC, 8),
+        (10_000 * BTC - 1, 11),
+        // 10k BTC and everything above it share the last bucket
+        (10_000 * BTC, 12),
+        (21_000_000 * BTC, 12),
+    ];
+    for (balance, idx) in cases {
+        let list = RichList::new(&holding(&[*balance]), 0);
+        for (bucket_idx, bucket) in list.buckets.iter().enumerate() {
+            let expected = if bucket_idx == *idx {
+                (1, *balance)
+            } else {
+                (0, 0)
+            };
+            assert_eq!(
+                (bucket.addresses, bucket.total),
+                expected,
+                "{} sat in bucket {}",
+                balance,
+                bucket_idx
+            );
+        }
+        assert_eq!(list.buckets[*idx].min, 10u64.pow(*idx as u32));
+    }
+}
+
+#[test]
+fn top_is_richest_first() {
+    let list = RichList::new(&holding(&[300, 500, 300, 100]), 3);
+    // Ties are ordered by wallet hash
+    assert_eq!(list.top, vec![(1, 500), (0, 300), (2, 300)]);
+    assert_eq!(list.buckets[2].addresses, 4);
+    assert_eq!(list.buckets[2].total, 1_200);
+}
@@ -0,0 +1,168 @@
+use bitcoincore_rpc::bitcoin::{Network, Script};
+use buttcoin::script::{
+    data_pushes, multisig_keys, p2pk_key, pubkey_to_p2pkh, script_to_address, script_type,
+};
+use buttcoin::ScriptType;
+
+fn address(script_hex: &str, network: Network) -> Result<String, String> {
+    let script = Script::from(hex::decode(script_hex).unwrap());
+    script_to_address(&script, network)
+}
+
+// Output scripts of each type with the address they pay to
+const MAINNET: &[(&str, &str, &str)] = &[
+    (
+        "p2pk, genesis coinbase",
+        "4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac",
+        "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa",
+    ),
+    (
+        "p2pkh",
+        "76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac",
+        "1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa",
+    ),
+    (
+        "p2sh",
+        "a914e9c3dd0c07aac76179ebc76a6c78d4d67c6c160a87",
+        "3P14159f73E4gFr7JterCCQh9QjiTjiZrG",
+    ),
+    (
+        "p2wpkh",
+        "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+        "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+    ),
+    (
+        "p2wsh",
+        "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262",
+        "bc1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3qccfmv3",
+    ),
+    (
+        "p2tr",
+        "512079be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798",
+        "bc1p0xlxvlhemja6c4dqv22uapctqupfhlxm9h8z3k2e72q4k9hcz7vqzk5jj0",
+    ),
+    (
+        "witness v16, unknown",
+        "6002751e",
+        "bc1sw50qgdz25j",
+    ),
+];
+
+#[test]
+fn mainnet_addresses() {
+    for (name, script_hex, expected) in MAINNET {
+        assert_eq!(
+            address(script_hex, Network::Bitcoin).as_deref(),
+            Ok(*expected),
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn testnet_and_regtest_prefixes() {
+    let wsh = "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262";
+    assert_eq!(
+        address(wsh, Network::Testnet).as_deref(),
+        Ok("tb1qrp33g0q5c5txsp9arysrx4k6zdkfs4nce4xj0gdcccefvpysxf3q0sl5k7")
+    );
+    assert_eq!(
+        address(
+            "0014751e76e8199196d454941c45d1b3a323f1433bd6",
+            Network::Regtest
+        )
+        .as_deref(),
+        Ok("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080")
+    );
+}
+
+#[test]
+fn invalid_scripts() {
+    // Witness v0 programs have to be 20 or 32 bytes
+    assert!(address("0010751e76e8199196d454941c45d1b3a323", Network::Bitcoin).is_err());
+    // OP_RETURN
+    assert!(address("6a0474657374", Network::Bitcoin).is_err());
+}
+
+#[test]
+fn script_types() {
+    let table: &[(&str, ScriptType)] = &[
+        (MAINNET[0].1, ScriptType::P2PK),
+        (MAINNET[1].1, ScriptType::P2PKH),
+        (MAINNET[2].1, ScriptType::P2SH),
+        (MAINNET[3].1, ScriptType::P2WPKH),
+        (MAINNET[4].1, ScriptType::P2WSH),
+        (MAINNET[5].1, ScriptType::P2TR),
+        (MAINNET[6].1, ScriptType::WitnessUnknown),
+        // 1-of-2 with a compressed and an uncompressed key
+        (
+            "512102c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee54104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5f52ae",
+            ScriptType::Multisig,
+        ),
+        ("6a0474657374", ScriptType::OpReturn),
+        ("6a", ScriptType::OpReturn),
+        ("0010751e76e8199196d454941c45d1b3a323", ScriptType::NonStandard),
+        ("51", ScriptType::NonStandard),
+        // Claims 2 keys but holds one
+        (
+            "512102c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee552ae",
+            ScriptType::NonStandard,
+        ),
+    ];
+    for (script_hex, expected) in table {
+        let script = Script::from(hex::decode(script_hex).unwrap());
+        assert_eq!(script_type(&script), *expected, "{}", script_hex);
+    }
+}
+
+#[test]
+fn multisig_participants() {
+    let genesis_key = &MAINNET[0].1[2..132];
+    let script_hex = format!(
+        "512102c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee541{}52ae",
+        genesis_key
+    );
+    let script = Script::from(hex::decode(script_hex).unwrap());
+    let (required, keys) = multisig_keys(&script).unwrap();
+    assert_eq!(required, 1);
+    assert_eq!(keys.len(), 2);
+    // The uncompressed key is credited to the same address as the genesis P2PK output
+    assert_eq!(
+        pubkey_to_p2pkh(keys[1], Network::Bitcoin).as_deref(),
+        Ok("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa")
+    );
+}
+
+#[test]
+fn op_return_pushes() {
+    let table: &[(&str, Vec<Vec<u8>>)] = &[
+        ("6a0474657374", vec![b"test".to_vec()]),
+        ("6a", vec![]),
+        // Opcodes between the pushes are skipped
+        ("6a5d0401020304", vec![vec![1, 2, 3, 4]]),
+        // A push past the end of the script
+        ("6a016105aabb", vec![b"a".to_vec()]),
+    ];
+    for (script_hex, expected) in table {
+        let script = Script::from(hex::decode(script_hex).unwrap());
+        assert_eq!(&data_pushes(&script), expected, "{}", script_hex);
+    }
+}
+
+#[test]
+fn p2pk_keys() {
+    let genesis = Script::from(hex::decode(MAINNET[0].1).unwrap());
+    assert_eq!(
+        p2pk_key(&genesis).map(hex::encode).as_deref(),
+        Some(&MAINNET[0].1[2..132])
+    );
+    let compressed = Script::from(
+        hex::decode("2102c6047f9441ed7d6d3045406e95c07cd85c778e4b8cef3ca7abac09b95c709ee5ac")
+            .unwrap(),
+    );
+    assert_eq!(p2pk_key(&compressed).map(|key| key.len()), Some(33));
+    // P2PKH has no key in the script
+    let p2pkh = Script::from(hex::decode(MAINNET[1].1).unwrap());
+    assert_eq!(p2pk_key(&p2pkh), None);
+}
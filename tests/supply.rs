@@ -0,0 +1,107 @@
+use bitcoincore_rpc::bitcoin::Network;
+use buttcoin::network::{cumulative_subsidy, halving_interval, subsidy};
+use buttcoin::supply::Audit;
+use buttcoin::{Block, BlockFees, Coinbase};
+
+const COIN: u64 = 100_000_000;
+
+#[test]
+fn halving_boundary() {
+    assert_eq!(subsidy(Network::Bitcoin, 0), 50 * COIN);
+    assert_eq!(subsidy(Network::Bitcoin, 209_999), 50 * COIN);
+    assert_eq!(subsidy(Network::Bitcoin, 210_000), 25 * COIN);
+    assert_eq!(subsidy(Network::Bitcoin, 420_000), 1_250_000_000);
+    assert_eq!(subsidy(Network::Testnet, 210_000), 25 * COIN);
+
+    assert_eq!(cumulative_subsidy(Network::Bitcoin, 0), 50 * COIN);
+    assert_eq!(
+        cumulative_subsidy(Network::Bitcoin, 209_999),
+        210_000 * 50 * COIN
+    );
+    assert_eq!(
+        cumulative_subsidy(Network::Bitcoin, 210_000),
+        210_000 * 50 * COIN + 25 * COIN
+    );
+}
+
+#[test]
+fn subsidy_runs_out() {
+    let interval = halving_interval(Network::Bitcoin);
+    // The last era paying 1 sat
+    assert_eq!(subsidy(Network::Bitcoin, 33 * interval - 1), 1);
+    assert_eq!(subsidy(Network::Bitcoin, 33 * interval), 0);
+    // Shifting by 64 or more would overflow, Core cuts off at 64 halvings
+    assert_eq!(subsidy(Network::Bitcoin, 64 * interval - 1), 0);
+    assert_eq!(subsidy(Network::Bitcoin, 64 * interval), 0);
+    assert_eq!(subsidy(Network::Bitcoin, u64::MAX), 0);
+
+    // The supply tops out just under 21 million coins
+    let total = 2_099_999_997_690_000;
+    assert_eq!(
+        cumulative_subsidy(Network::Bitcoin, 33 * interval - 1),
+        total
+    );
+    assert_eq!(cumulative_subsidy(Network::Bitcoin, 64 * interval), total);
+    assert_eq!(cumulative_subsidy(Network::Bitcoin, 100 * interval), total);
+}
+
+#[test]
+fn regtest_halves_every_150_blocks() {
+    assert_eq!(halving_interval(Network::Regtest), 150);
+    assert_eq!(subsidy(Network::Regtest, 149), 50 * COIN);
+    assert_eq!(subsidy(Network::Regtest, 150), 25 * COIN);
+    assert_eq!(subsidy(Network::Regtest, 300), 1_250_000_000);
+    assert_eq!(cumulative_subsidy(Network::Regtest, 149), 150 * 50 * COIN);
+    assert_eq!(
+        cumulative_subsidy(Network::Regtest, 150),
+        150 * 50 * COIN + 25 * COIN
+    );
+}
+
+fn block(height: u64, claimed: u64, fees: Option<u64>) -> Block {
+    let mut block = Block::new(height, String::new(), 0);
+    block.coinbase = Some(Coinbase {
+        height: Some(height),
+        subsidy: subsidy(Network::Regtest, height),
+        claimed,
+        ..Default::default()
+    });
+    block.fees = fees.map(|total| BlockFees {
+        total,
+        ..Default::default()
+    });
+    block
+}
+
+#[test]
+fn audit_claims() {
+    let mut audit = Audit::new(Network::Regtest);
+    for height in 0..149 {
+        audit.add_block(&block(height, 50 * COIN, Some(0))).unwrap();
+    }
+    // Leaves 1000 sat of fees and 1 coin of subsidy unclaimed
+    audit
+        .add_block(&block(149, 49 * COIN + 1000, Some(2000)))
+        .unwrap();
+    // Claims 1 sat more than allowed
+    audit
+        .add_block(&block(150, 25 * COIN + 501, Some(500)))
+        .unwrap();
+    // Fees unknown, counted up to the subsidy
+    audit.add_block(&block(151, 25 * COIN + 700, None)).unwrap();
+
+    assert_eq!(audit.blocks, 152);
+    assert_eq!(audit.under_claimed_blocks, vec![(149, COIN + 1000)]);
+    assert_eq!(audit.under_claimed, COIN + 1000);
+    assert_eq!(audit.over_claimed_blocks, vec![(150, 1)]);
+    assert_eq!(audit.unresolved, 1);
+    assert!(audit.bip34_mismatches.is_empty());
+    assert_eq!(audit.theoretical(), 150 * 50 * COIN + 2 * 25 * COIN);
+    assert_eq!(
+        audit.issued,
+        149 * 50 * COIN + (49 * COIN - 1000) + (25 * COIN + 1) + 25 * COIN
+    );
+    assert_eq!(audit.eras.len(), 2);
+    assert_eq!(audit.eras[1].first_height, 150);
+    assert_eq!(audit.eras[1].blocks, 2);
+}
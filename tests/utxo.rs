@@ -0,0 +1,96 @@
+use buttcoin::utxo::{Partition, UtxoSet};
+use buttcoin::{ScriptType, Transaction, Vin, Vout};
+
+fn coinbase(hash: u64, values: &[u64]) -> Transaction {
+    let mut tx = Transaction::new(hash, format!("{:064x}", hash));
+    for value in values {
+        tx.add_vout(Vout::new(*value, ScriptType::P2PKH, Some(hash), None));
+    }
+    tx
+}
+
+fn spend(hash: u64, outpoints: &[(u64, u32)], values: &[u64]) -> Transaction {
+    let mut tx = coinbase(hash, values);
+    for (txid_hash, vout_idx) in outpoints {
+        tx.add_vin(Vin::new(*txid_hash, *vout_idx));
+    }
+    tx
+}
+
+#[test]
+fn partition_split() {
+    let (low, high) = Partition::ALL.split();
+    assert_eq!(low, Partition { index: 0, count: 2 });
+    assert_eq!(high, Partition { index: 1, count: 2 });
+    let (low_low, low_high) = low.split();
+    assert_eq!(low_low, Partition { index: 0, count: 4 });
+    assert_eq!(low_high, Partition { index: 2, count: 4 });
+    let (high_low, high_high) = high.split();
+    assert_eq!(high_low, Partition { index: 1, count: 4 });
+    assert_eq!(high_high, Partition { index: 3, count: 4 });
+
+    // Every hash is in exactly one half, and in a half only if it is in the partition split
+    let quarters = [low_low, low_high, high_low, high_high];
+    for txid_hash in [0, 1, 2, 3, 4, 5, 1 << 40, u64::MAX, u64::MAX - 1] {
+        assert!(Partition::ALL.contains(txid_hash));
+        assert!(low.contains(txid_hash) != high.contains(txid_hash));
+        assert_eq!(
+            quarters
+                .iter()
+                .filter(|quarter| quarter.contains(txid_hash))
+                .count(),
+            1
+        );
+        assert_eq!(
+            low_low.contains(txid_hash) || low_high.contains(txid_hash),
+            low.contains(txid_hash)
+        );
+    }
+}
+
+#[test]
+fn partitions_hold_their_outputs() {
+    let (low, high) = Partition::ALL.split();
+    let mut low_set = UtxoSet::new(low);
+    let mut high_set = UtxoSet::new(high);
+    for set in [&mut low_set, &mut high_set] {
+        set.add_transaction(1, &coinbase(10, &[50]));
+        set.add_transaction(2, &coinbase(11, &[50]));
+        // Spends 10:0, paying 7:0 and 7:1
+        set.add_transaction(3, &spend(7, &[(10, 0)], &[20, 30]));
+    }
+    // Even hashes are in the low half, odd ones in the high half
+    assert!(low_set.is_empty());
+    assert!(!high_set.contains(10, 0));
+    assert!(high_set.contains(11, 0));
+    assert!(high_set.contains(7, 0) && high_set.contains(7, 1));
+    assert_eq!(high_set.len(), 3);
+}
+
+#[test]
+fn bip30_overwrites() {
+    let mut set = UtxoSet::new(Partition::ALL);
+    // Heights 91812 and 91842 on mainnet have the same coinbase txid
+    set.add_transaction(91_812, &coinbase(42, &[5_000_000_000]));
+    set.add_transaction(91_842, &coinbase(42, &[5_000_000_000]));
+    assert_eq!(set.get_bip30_overwritten(), 1);
+    assert_eq!(set.len(), 1);
+
+    // Spent outputs aren't overwritten when the txid comes back
+    set.add_transaction(91_900, &spend(43, &[(42, 0)], &[4_999_990_000]));
+    set.add_transaction(91_901, &coinbase(42, &[5_000_000_000]));
+    assert_eq!(set.get_bip30_overwritten(), 1);
+
+    // OP_RETURN outputs never enter the set, so they can't be overwritten either
+    let mut tx = coinbase(44, &[]);
+    tx.add_vout(Vout::new(0, ScriptType::OpReturn, None, None));
+    set.add_transaction(91_902, &tx);
+    set.add_transaction(91_903, &tx);
+    assert_eq!(set.get_bip30_overwritten(), 1);
+    assert!(!set.contains(44, 0));
+
+    // The genesis coinbase is never added
+    let mut genesis = UtxoSet::new(Partition::ALL);
+    genesis.add_transaction(0, &coinbase(1, &[5_000_000_000]));
+    assert!(genesis.is_empty());
+}